@@ -0,0 +1,50 @@
+//! Compares the events of pulldown-cmark with the ones of `ParserOffsetIter`
+//! when the wikilinks are disabled, to check that the passthrough adds no overhead.
+//!
+//! Run with `cargo bench --bench passthrough`
+
+use pulldown_cmark::{Options, Parser};
+use pulldown_cmark_wikilink::ParserOffsetIter;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// the mean time of `f` over `runs` runs, after a warm up
+fn measure(runs: u32, mut f: impl FnMut()) -> Duration {
+    for _ in 0..runs / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let paragraph = "# A title\n\nsome *text* with a [link](url), a [[wikilink|alias]] and `code`.\n\n\
+                     - a list\n- with [[items]]\n\n```\ncode [[block]]\n```\n\n";
+    let source = paragraph.repeat(10_000);
+    let runs = 20;
+
+    let upstream = measure(runs, || {
+        for x in Parser::new_ext(&source, Options::all()).into_offset_iter() {
+            black_box(x);
+        }
+    });
+    let passthrough = measure(runs, || {
+        for x in ParserOffsetIter::new_ext(&source, Options::all(), false) {
+            black_box(x);
+        }
+    });
+    let wikilinks = measure(runs, || {
+        for x in ParserOffsetIter::new_ext(&source, Options::all(), true) {
+            black_box(x);
+        }
+    });
+
+    println!("{} bytes, mean of {runs} runs", source.len());
+    println!("into_offset_iter:                {upstream:?}");
+    println!("ParserOffsetIter without links:  {passthrough:?}");
+    println!("ParserOffsetIter with links:     {wikilinks:?}");
+}
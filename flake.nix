@@ -23,6 +23,10 @@
                 packages.default = buildPackage {
                     src = ./.;
                 };
+                checks.no-std = buildPackage {
+                    src = ./.;
+                    cargoBuildOptions = opts: opts ++ ["--no-default-features"];
+                };
                 devShell = pkgs.mkShell {
                     nativeBuildInputs = [
                         rust-toolchain
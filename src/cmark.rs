@@ -0,0 +1,177 @@
+use crate::{Item, ParserOffsetIter, WikiLink, WikiLinkType};
+use pulldown_cmark::Event;
+
+use alloc::string::String;
+
+/// An iterator over the events, where each wikilink is replaced by its source,
+/// like `[[target|alias]]`, in a single [`Event::Html`].
+///
+/// When fed to `pulldown_cmark_to_cmark::cmark`, the wikilinks are
+/// written back as they were, instead of `[alias](target "wiki")`.
+///
+/// Returned by [`ParserOffsetIter::to_cmark`]
+pub struct ToCmark<'a, 'b> {
+    pub(crate) parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for ToCmark<'a, 'b> {
+    type Item = Event<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.parser.next_item()? {
+            Item::Event(e, _) => e,
+            Item::Link(l) => match self.parser.link_as_text(&l) {
+                Some((text, _)) => text,
+                None => {
+                    let base = self.parser.base_offset;
+                    Event::Html(self.parser.source[l.range.start - base..l.range.end - base].into())
+                }
+            },
+        })
+    }
+}
+
+/// An iterator over the events, where each wikilink is replaced by the equivalent
+/// CommonMark link, like `[alias](target)`, in a single [`Event::Html`].
+///
+/// When fed to `pulldown_cmark_to_cmark::cmark`, the document can be read
+/// by tools that don't know wikilinks.
+///
+/// Returned by [`ParserOffsetIter::to_standard_markdown`]
+pub struct ToStandardMarkdown<'a, 'b> {
+    pub(crate) parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for ToStandardMarkdown<'a, 'b> {
+    type Item = Event<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.parser.next_item()? {
+            Item::Event(e, _) => e,
+            Item::Link(l) => match self.parser.link_as_text(&l) {
+                Some((text, _)) => text,
+                None => Event::Html(standard_link(&l).into()),
+            },
+        })
+    }
+}
+
+/// `standard_link(link)` is `link` written as an inline link, like `[alias](target)`,
+/// or an image for an embed. The kind of the link, if any, is its title
+fn standard_link(link: &WikiLink) -> String {
+    let mut s = String::new();
+    if link.link_type == WikiLinkType::Embed {
+        s.push('!');
+    }
+    s.push('[');
+    for c in link.display.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '_' | '`' | '<' | '&' | '~') {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    s.push_str("](");
+
+    // the destination is written between `<` and `>` if it can't be written as it is
+    let target = &*link.target;
+    let wrap = target.is_empty() || target.contains(|c: char| c.is_whitespace() || c.is_control() || "()<>".contains(c));
+    if wrap {
+        s.push('<');
+    }
+    for c in target.chars() {
+        if c == '\\' || (wrap && matches!(c, '<' | '>')) {
+            s.push('\\');
+        }
+        s.push(c);
+    }
+    if wrap {
+        s.push('>');
+    }
+
+    if let Some(kind) = &link.kind {
+        s.push_str(" \"");
+        for c in kind.chars() {
+            if matches!(c, '\\' | '"') {
+                s.push('\\');
+            }
+            s.push(c);
+        }
+        s.push('"');
+    }
+    s.push(')');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, Tag, WikiOptions};
+
+    #[test]
+    fn round_trip() {
+        let s = "see [[a | b]] and ![[c.png|100]]";
+        let wiki_options = WikiOptions::new().embeds(true).trim_spaces(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .to_cmark()
+            .collect();
+
+        assert_eq!(events[2], Event::Html("[[a | b]]".into()));
+
+        let written: String = events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Text(t) | Event::Html(t) => Some(&**t),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(written, s);
+
+        // the written document has the same wikilinks
+        let links = |s: &str| -> Vec<_> {
+            let wiki_options = WikiOptions::new().embeds(true).trim_spaces(true);
+            ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+                .wikilinks()
+                .map(|l| (l.target.to_string(), l.display.to_string(), l.link_type, l.embed_params))
+                .collect()
+        };
+        assert_eq!(links(&written), links(s));
+        assert_eq!(links(&written).len(), 2);
+    }
+
+    #[test]
+    fn standard_links() {
+        let s = "[[a b|the [1] a_b]] [[c]] ![[d.png|alt]] {{e}}";
+        let wiki_options = WikiOptions::new().embeds(true).delimiters(&[("{{", "}}", "template")]);
+        let links: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .to_standard_markdown()
+            .filter(|e| matches!(e, Event::Html(_)))
+            .collect();
+
+        assert_eq!(links, vec![
+                   Event::Html("[the \\[1\\] a\\_b](<a b>)".into()),
+                   Event::Html("[c](c)".into()),
+                   Event::Html("![alt](d.png)".into()),
+                   Event::Html("[e](e \"template\")".into()),
+        ]);
+    }
+
+    #[test]
+    fn standard_link_round_trip() {
+        let s = "see [[a (b)|the link]]";
+        let written: String = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .to_standard_markdown()
+            .filter_map(|e| match e {
+                Event::Text(t) | Event::Html(t) => Some(t.to_string()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(written, "see [the link](<a (b)>)");
+
+        let events: Vec<_> = pulldown_cmark::Parser::new(&written).collect();
+        assert_eq!(events[2], Event::Start(Tag::Link {
+            link_type: pulldown_cmark::LinkType::Inline,
+            dest_url: "a (b)".into(),
+            title: "".into(),
+            id: "".into(),
+        }));
+        assert_eq!(events[3], Event::Text("the link".into()));
+    }
+}
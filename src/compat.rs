@@ -0,0 +1,69 @@
+use crate::{OffsetIter, Options, ParserOffsetIter, WikiOptions};
+use pulldown_cmark::Event;
+
+/// A drop-in replacement for [`pulldown_cmark::Parser`], that also parses wikilinks.
+///
+/// As upstream, it iterates over the events alone,
+/// and [`into_offset_iter`](Self::into_offset_iter) gives their ranges too.
+///
+/// Differences with upstream:
+/// - the wikilinks are always parsed, use [`ParserOffsetIter::new_ext`] to disable them
+/// - there is no callback for broken links, and `'b` only exists so that
+///   the type has the same shape as upstream
+pub struct Parser<'a, 'b> {
+    inner: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    /// Creates a new event iterator for a markdown string, without any extension
+    pub fn new(source: &'a str) -> Self {
+        Self::new_ext(source, Options::empty())
+    }
+
+    /// Creates a new event iterator for a markdown string with given options
+    pub fn new_ext(source: &'a str, options: Options) -> Self {
+        Self { inner: ParserOffsetIter::new_ext(source, options, true) }
+    }
+
+    /// Same as [`new_ext`](Self::new_ext), with wikilinks parsed according to `wiki_options`
+    pub fn new_with(source: &'a str, options: Options, wiki_options: WikiOptions) -> Self {
+        Self { inner: ParserOffsetIter::new_with(source, options, wiki_options) }
+    }
+
+    /// Consumes the event iterator and produces an iterator that produces
+    /// `(Event, Range)` pairs, where the `Range` value maps to the corresponding
+    /// range in the markdown source.
+    pub fn into_offset_iter(self) -> OffsetIter<'a, 'b> {
+        self.inner
+    }
+}
+
+impl<'a, 'b> Iterator for Parser<'a, 'b> {
+    type Item = Event<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(e, _)| e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LinkType, Tag};
+
+    #[test]
+    fn pulldown_style() {
+        let s = "see [[a]]";
+        let link = Event::Start(Tag::Link {
+            link_type: LinkType::Inline,
+            dest_url: "a".into(),
+            title: "wiki".into(),
+            id: "".into(),
+        });
+
+        let events: Vec<_> = Parser::new(s).collect();
+        assert_eq!(events[2], link);
+
+        let events: Vec<_> = Parser::new_ext(s, Options::all()).into_offset_iter().collect();
+        assert_eq!(events[2], (link, 4..9));
+    }
+}
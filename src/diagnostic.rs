@@ -0,0 +1,81 @@
+use crate::wikilink::has_traversal;
+use crate::WikiLink;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Something that is probably a mistake in the source,
+/// found by [`check_links`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    /// the part of the source that is affected
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// the link is the same as the one right before it, like in `[[a]][[a]]`
+    AdjacentDuplicate,
+    /// the target has a `..` segment, like `[[../../etc/passwd]]`,
+    /// so it may point outside of the folder of the notes
+    PathTraversal,
+    /// the document has more wikilinks than
+    /// [`WikiOptions::max_wikilinks`](crate::WikiOptions::max_wikilinks),
+    /// and the rest of it was not parsed for wikilinks.
+    /// Reported by [`parse_document`](crate::parse_document)
+    TooManyWikilinks,
+}
+
+/// Looks for the usual mistakes in the wikilinks of a document,
+/// given in the order of the source.
+pub fn check_links<'a>(links: impl IntoIterator<Item = WikiLink<'a>>) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut previous: Option<WikiLink<'a>> = None;
+    for link in links {
+        if !link.external && has_traversal(&link.target) {
+            diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::PathTraversal,
+                range: link.target_range.clone(),
+            });
+        }
+        if let Some(p) = &previous {
+            if p.range.end == link.range.start && p.target == link.target && p.display == link.display {
+                diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::AdjacentDuplicate,
+                    range: link.range.clone(),
+                });
+            }
+        }
+        previous = Some(link);
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, ParserOffsetIter};
+
+    fn check(s: &str) -> Vec<Diagnostic> {
+        check_links(ParserOffsetIter::new_ext(s, Options::all(), true).wikilinks())
+    }
+
+    #[test]
+    fn adjacent_duplicate() {
+        assert_eq!(check("[[a]][[a]]"), vec![
+                   Diagnostic { kind: DiagnosticKind::AdjacentDuplicate, range: 5..10 },
+        ]);
+        assert_eq!(check("[[a]] [[a]]"), vec![]);
+        assert_eq!(check("[[a]][[b]]"), vec![]);
+    }
+
+    #[test]
+    fn path_traversal() {
+        assert_eq!(check("see [[../../etc/passwd|x]]"), vec![
+                   Diagnostic { kind: DiagnosticKind::PathTraversal, range: 6..22 },
+        ]);
+        assert_eq!(check("[[notes/../a]] [[a..b]]").len(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{check_links, Diagnostic, DiagnosticKind, Item, Options, ParseStats, ParserOffsetIter, WikiLink, WikiLinks, WikiOptions};
+use pulldown_cmark::{CowStr, Event};
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A markdown document that owns its source, and the options to parse it.
+///
+/// The events borrow the source, so they are not stored: parsing is cheap
+/// and deterministic, and the events are produced again each time
+/// the document is iterated. This avoids borrowing the source from outside.
+#[derive(Debug, Clone)]
+pub struct WikiDocument {
+    source: String,
+    options: Options,
+    wiki_options: Arc<WikiOptions>,
+}
+
+impl WikiDocument {
+    pub fn new(source: String, options: Options) -> Self {
+        Self::new_with(source, options, WikiOptions::default())
+    }
+
+    /// a document where the wikilinks are parsed according to `wiki_options`
+    pub fn new_with(source: String, options: Options, wiki_options: WikiOptions) -> Self {
+        Self { source, options, wiki_options: Arc::new(wiki_options) }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// an iterator over the `(Event, Range)` pairs of the document
+    pub fn iter(&self) -> ParserOffsetIter<'_, '_> {
+        ParserOffsetIter::new_shared(&self.source, self.options, self.wiki_options.clone())
+    }
+
+    /// an iterator over the wikilinks of the document
+    pub fn wikilinks(&self) -> WikiLinks<'_, '_> {
+        self.iter().wikilinks()
+    }
+
+    /// an iterator over the target of each wikilink, in document order
+    pub fn targets(&self) -> impl Iterator<Item = CowStr<'_>> {
+        self.wikilinks().map(|l| l.target)
+    }
+}
+
+impl<'a> IntoIterator for &'a WikiDocument {
+    type Item = (Event<'a>, Range<usize>);
+    type IntoIter = ParserOffsetIter<'a, 'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Everything that is known about a document, once parsed.
+///
+/// Returned by [`parse_document`]
+#[derive(Debug, Clone)]
+pub struct ParsedDocument<'a> {
+    /// the `(Event, Range)` pairs, as produced by [`ParserOffsetIter`]
+    pub events: Vec<(Event<'a>, Range<usize>)>,
+    /// the wikilinks, in the order of the source
+    pub wikilinks: Vec<WikiLink<'a>>,
+    /// the mistakes found by [`check_links`], and a
+    /// [`DiagnosticKind::TooManyWikilinks`] if the parse stopped early
+    pub diagnostics: Vec<Diagnostic>,
+    pub stats: ParseStats,
+}
+
+/// Parses `source` once, and collects its events, its wikilinks,
+/// their diagnostics and the stats of the parse.
+pub fn parse_document(source: &str, options: Options, wiki_options: WikiOptions) -> ParsedDocument<'_> {
+    let mut parser = ParserOffsetIter::new_with(source, options, wiki_options);
+    let mut events = Vec::new();
+    let mut wikilinks = Vec::new();
+    while let Some(item) = parser.next_item() {
+        match item {
+            Item::Event(e, r) => events.push((e, r)),
+            Item::Link(l) => {
+                events.extend(parser.link_events(l.clone()));
+                wikilinks.push(l);
+            }
+        }
+    }
+    let stats = parser.stats();
+    let mut diagnostics = check_links(wikilinks.iter().cloned());
+    if let Some(start) = stats.limit_reached_at {
+        diagnostics.push(Diagnostic { kind: DiagnosticKind::TooManyWikilinks, range: start..source.len() });
+    }
+    ParsedDocument {
+        events,
+        diagnostics,
+        wikilinks,
+        stats,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterate_twice() {
+        let doc = WikiDocument::new("[[a]] and [[b|c]]".to_string(), Options::all());
+
+        let first: Vec<_> = doc.iter().collect();
+        let second: Vec<_> = (&doc).into_iter().collect();
+
+        assert_eq!(first, second);
+        assert_eq!(doc.targets().collect::<Vec<_>>(), vec!["a".into(), CowStr::from("b")]);
+    }
+
+    #[test]
+    fn document_with_options() {
+        let wiki_options = WikiOptions::new().target_prefix("/wiki/");
+        let doc = WikiDocument::new_with("[[a]] and [[b|c]]".to_string(), Options::all(), wiki_options);
+
+        assert_eq!(doc.targets().collect::<Vec<_>>(), vec!["/wiki/a".into(), CowStr::from("/wiki/b")]);
+        assert_eq!(doc.iter().collect::<Vec<_>>(), doc.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn parsed_document() {
+        let s = "[[a]][[a]] and [[../b|c]]";
+        let doc = parse_document(s, Options::all(), WikiOptions::new());
+
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        assert_eq!(doc.events, events);
+        assert_eq!(doc.wikilinks.iter().map(|l| &*l.target).collect::<Vec<_>>(), vec!["a", "a", "../b"]);
+        assert_eq!(doc.diagnostics.len(), 2);
+        assert_eq!(doc.stats, ParseStats { wikilinks: 3, path_traversals: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn too_many_wikilinks() {
+        let s = "[[a]] [[b]] [[c]]";
+        let doc = parse_document(s, Options::all(), WikiOptions::new().max_wikilinks(1));
+
+        assert_eq!(doc.wikilinks.len(), 1);
+        assert_eq!(doc.diagnostics, vec![Diagnostic { kind: DiagnosticKind::TooManyWikilinks, range: 5..17 }]);
+
+        // a document with exactly `max` links, and brackets that are not a link after them
+        let s = "[[a]] [[b]]\n\nx [[ not a link";
+        let doc = parse_document(s, Options::all(), WikiOptions::new().max_wikilinks(2));
+        assert_eq!(doc.wikilinks.len(), 2);
+        assert_eq!(doc.diagnostics, vec![]);
+    }
+}
@@ -0,0 +1,66 @@
+use pulldown_cmark::{Event, Tag};
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::ops::Range;
+
+/// A readable dump of `events`, one per line, like `0..20 Text("some text") "some text"`,
+/// to compare the events in tests or to show them in a bug report.
+///
+/// Each line has the range of the event, the event and the part of `source` in the range.
+/// The events are indented by their nesting in the start and end tags.
+///
+/// ```
+/// use pulldown_cmark_wikilink::{dump_events, Options, ParserOffsetIter};
+///
+/// let s = "[[a]]";
+/// let dump = dump_events(s, ParserOffsetIter::new_ext(s, Options::empty(), true));
+/// assert_eq!(dump.lines().nth(2), Some("    2..3 Text(\"a\") \"a\""));
+/// ```
+pub fn dump_events<'a>(source: &str, events: impl IntoIterator<Item = (Event<'a>, Range<usize>)>) -> String {
+    let mut dump = String::new();
+    let mut depth: usize = 0;
+    for (e, r) in events {
+        if let Event::End(_) = e {
+            depth = depth.saturating_sub(1);
+        }
+        let _ = write!(dump, "{:width$}{:?} ", "", r, width = 2 * depth);
+        let _ = match &e {
+            Event::Text(t) => write!(dump, "Text({:?})", &**t),
+            Event::Code(t) => write!(dump, "Code({:?})", &**t),
+            Event::Html(t) => write!(dump, "Html({:?})", &**t),
+            Event::Start(Tag::Link { dest_url, title, .. }) => {
+                write!(dump, "Start(Link({:?}, title: {:?}))", &**dest_url, &**title)
+            }
+            Event::Start(Tag::Image { dest_url, title, .. }) => {
+                write!(dump, "Start(Image({:?}, title: {:?}))", &**dest_url, &**title)
+            }
+            e => write!(dump, "{e:?}"),
+        };
+        let _ = writeln!(dump, " {:?}", source.get(r).unwrap_or_default());
+        if let Event::Start(_) = e {
+            depth += 1;
+        }
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, ParserOffsetIter};
+
+    #[test]
+    fn dump_no_alias() {
+        let s = "here is a wikilink: [[link]]";
+        let dump = dump_events(s, ParserOffsetIter::new_ext(s, Options::all(), true));
+
+        assert_eq!(dump, r#"0..28 Start(Paragraph) "here is a wikilink: [[link]]"
+  0..20 Text("here is a wikilink: ") "here is a wikilink: "
+  20..28 Start(Link("link", title: "wiki")) "[[link]]"
+    22..26 Text("link") "link"
+  20..28 End(Link) "[[link]]"
+0..28 End(Paragraph) "here is a wikilink: [[link]]"
+"#);
+    }
+}
@@ -0,0 +1,120 @@
+//! HTML rendering, with the same functions as [`pulldown_cmark::html`].
+//!
+//! Links with the `wiki` marker are rendered as `<a href="target" class="wikilink">`,
+//! transclusions as `<a href="target" class="wikilink transclusion">`,
+//! roam block refs as `<a href="uid" class="wikilink block-ref">`,
+//! broken links as `<a href="target" class="wikilink broken">`,
+//! and the marker is removed from embedded images.
+//! Everything else is rendered by pulldown-cmark.
+
+use pulldown_cmark::escape::escape_href;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use crate::{BROKEN_LINK_MARKER, ROAM_BLOCK_REF_MARKER, TRANSCLUSION_MARKER, WIKI_MARKER};
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+/// Iterates over the events, and pushes the HTML output to the string
+pub fn push_html<'a, I>(s: &mut String, iter: I)
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    pulldown_cmark::html::push_html(s, WikiHtml::new(iter))
+}
+
+/// Iterates over the events, and writes the HTML output to `writer` as it goes.
+///
+/// The writer is flushed after each line, so that a client receives
+/// every block as soon as it is rendered.
+#[cfg(feature = "std")]
+pub fn write_html<'a, I, W>(writer: W, iter: I) -> io::Result<()>
+where
+    I: Iterator<Item = Event<'a>>,
+    W: Write,
+{
+    let mut writer = LineFlush(writer);
+    pulldown_cmark::html::write_html(&mut writer, WikiHtml::new(iter))?;
+    writer.flush()
+}
+
+/// replaces the wiki-marked links by the equivalent raw html
+struct WikiHtml<I> {
+    events: I,
+    inside_wikilink: bool,
+}
+
+impl<I> WikiHtml<I> {
+    fn new(events: I) -> Self {
+        Self { events, inside_wikilink: false }
+    }
+}
+
+impl<'a, I: Iterator<Item = Event<'a>>> Iterator for WikiHtml<I> {
+    type Item = Event<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.events.next()? {
+            Event::Start(Tag::Link { dest_url, title, .. })
+                if [WIKI_MARKER, TRANSCLUSION_MARKER, ROAM_BLOCK_REF_MARKER, BROKEN_LINK_MARKER].contains(&&*title) =>
+            {
+                self.inside_wikilink = true;
+                let mut html = String::from("<a href=\"");
+                let _ = escape_href(&mut html, &dest_url);
+                html.push_str(match &*title {
+                    TRANSCLUSION_MARKER => "\" class=\"wikilink transclusion\">",
+                    ROAM_BLOCK_REF_MARKER => "\" class=\"wikilink block-ref\">",
+                    BROKEN_LINK_MARKER => "\" class=\"wikilink broken\">",
+                    _ => "\" class=\"wikilink\">",
+                });
+                Event::Html(html.into())
+            }
+            Event::End(TagEnd::Link) if self.inside_wikilink => {
+                self.inside_wikilink = false;
+                Event::Html("</a>".into())
+            }
+            Event::Start(Tag::Image { link_type, dest_url, title, id }) if &*title == WIKI_MARKER => {
+                Event::Start(Tag::Image { link_type, dest_url, title: "".into(), id })
+            }
+            other => other,
+        })
+    }
+}
+
+/// flushes the inner writer each time a newline is written
+#[cfg(feature = "std")]
+struct LineFlush<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for LineFlush<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.0.write(buf)?;
+        if buf[..n].contains(&b'\n') {
+            self.0.flush()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{Options, ParserOffsetIter};
+
+    #[test]
+    fn write_into_vec() {
+        let s = "see [[a b|the link]]";
+        let events = ParserOffsetIter::new_ext(s, Options::all(), true).map(|(e, _)| e);
+
+        let mut output = Vec::new();
+        write_html(&mut output, events).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<p>see <a href=\"a%20b\" class=\"wikilink\">the link</a></p>\n"
+        );
+    }
+}
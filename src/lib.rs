@@ -1,64 +1,219 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub use pulldown_cmark::Parser as _Parser;
 pub use pulldown_cmark::OffsetIter as _OffsetIter;
 pub use pulldown_cmark::*;
 
 // shadows the original types so that the user don't use the wrong one
-pub type Parser<'a, 'b> = ParserOffsetIter<'a, 'b>;
+mod compat;
+pub use compat::Parser;
 pub type OffsetIter<'a, 'b> = ParserOffsetIter<'a, 'b>;
 
 mod token;
 use token::{Lexer, Token};
 
+mod wikilink;
+pub use wikilink::{
+    EmbedParams, TargetParts, WikiLink, WikiLinkType, BROKEN_LINK_MARKER, ROAM_BLOCK_REF_MARKER, TRANSCLUSION_MARKER,
+    WIKI_MARKER,
+};
+
+mod document;
+pub use document::{parse_document, ParsedDocument, WikiDocument};
+
+mod options;
+pub use options::{DisplayMode, FragmentStyle, WikiOptions, DEFAULT_IMAGE_EXTENSIONS, INVISIBLE_CHARS};
+
+mod transform;
+pub use transform::{ProcessedLink, TargetTransform, Then, WikiLinkProcessor};
+
+mod position;
+pub use position::{context_around, LineIndex, Position, WithPositions};
+
+pub mod html;
+
+mod cmark;
+pub use cmark::{ToCmark, ToStandardMarkdown};
+
+mod diagnostic;
+pub use diagnostic::{check_links, Diagnostic, DiagnosticKind};
+
+mod stats;
+pub use stats::ParseStats;
+
+mod syntax;
+pub use syntax::{supported_syntax, WikiSyntaxExample, SUPPORTED_SYNTAX};
+
+mod node;
+pub use node::{parse_to_nodes, Node};
+
+mod transclude;
+pub use transclude::Transclusions;
+
+mod dump;
+pub use dump::dump_events;
+
 use Token::*;
 
 use core::ops::Range;
 use core::iter::Peekable;
-use std::vec;
-
-
+use alloc::sync::Arc;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+
+
+/// merges consecutive text events and drops empty ones,
+/// unless `join` is false, in which case the events are passed through untouched.
+///
+/// Only the texts that directly follow each other are merged: a start or end tag,
+/// or a line break, is never crossed, so a text is always inside a single block
 struct TextJoiner<'a, 'b> {
     source: &'a str,
     parser: Peekable<_OffsetIter<'a, 'b>>,
+    join: bool,
+    /// see [`WikiOptions::bridge_inline_html`]
+    bridge_html: bool,
 }
 
 impl<'a, 'b> TextJoiner<'a, 'b> {
-    fn new_ext(source: &'a str, options: Options) -> Self {
-        Self {
+    fn new_ext(source: &'a str, options: Options, join: bool) -> Self {
+        let mut joiner = Self {
             source,
             parser: _Parser::new_ext(source, options)
                 .into_offset_iter()
                 .peekable(),
+            join,
+            bridge_html: false,
+        };
+        if join {
+            joiner.skip_empty();
+        }
+        joiner
+    }
+
+    fn peek(&mut self) -> Option<&(Event<'a>, Range<usize>)> {
+        self.parser.peek()
+    }
+
+    /// drops the empty text events, so that the peeked event (if any)
+    /// is always going to be returned
+    fn skip_empty(&mut self) {
+        while let Some((Event::Text(x), _)) = self.parser.peek() {
+            if !x.is_empty() {
+                break
+            }
+            self.parser.next();
         }
     }
 }
 
+/// `is_blank(source)` is true if `source` only has spaces, tabs and newlines,
+/// so that it has no markdown event. Other whitespace, like a non-breaking space, is text
+fn is_blank(source: &str) -> bool {
+    source.bytes().all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r'))
+}
+
+/// `opens_link(text)` is true if `text` has a `[[` that is not closed yet
+fn opens_link(text: &str) -> bool {
+    match text.rfind("[[") {
+        Some(i) => !text[i..].contains("]]"),
+        None => false,
+    }
+}
+
 impl<'a, 'b> Iterator for TextJoiner<'a, 'b> {
     type Item=(Event<'a>, Range<usize>);
     fn next(&mut self) -> Option<Self::Item> {
-        match self.parser.peek()? {
-            (Event::Text(x), _) if x.is_empty() => {
-                self.parser.next();
-                self.next()
-            },
+        if !self.join {
+            return self.parser.next()
+        }
+
+        let item = match self.parser.peek()? {
             (Event::Text(_), range) => {
                 let start = range.start;
                 let mut end = range.end;
-                while let Some((Event::Text(_), _)) = self.parser.peek() {
+                loop {
+                    match self.parser.peek() {
+                        Some((Event::Text(_), _)) => {}
+                        Some((Event::Html(_), r))
+                            if self.bridge_html && r.start == end && opens_link(&self.source[start..end]) => {}
+                        _ => break,
+                    }
                     end = self.parser.next().unwrap().1.end;
                 }
 
-                Some((Event::Text(self.source[start..end].into()), start..end))
+                (Event::Text(self.source[start..end].into()), start..end)
 
             },
-            _ => self.parser.next()
+            _ => self.parser.next()?
+        };
+        self.skip_empty();
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if !self.join {
+            return self.parser.size_hint()
         }
+        // the peeked event is always returned, 
+        // but all the following ones could be merged with it
+        let (lower, upper) = self.parser.size_hint();
+        (lower.min(1), upper)
     }
 }
 
+/// A parser of the wikilinks in a part of a source, without any markdown processing.
+///
+/// It yields the [`Text`](Event::Text) events and the events of the wikilinks
+/// found in the range it is given, and each range is relative to the whole `source`,
+/// so that a part of a document can be parsed again after an edit.
 pub struct WikiParser<'a, 'b> {
     source: &'a str,
+    /// start of the range that is parsed
+    start: usize,
+    /// end of the range that is parsed
+    end: usize,
     lexer: Peekable<Lexer<'b>>,
     buffer: vec::IntoIter<(Event<'a>, Range<usize>)>,
+    options: Arc<WikiOptions>,
+    /// the number of characters stripped from the targets, since it was last read
+    stripped_chars: usize,
+    /// the number of targets with a `..` segment, since it was last read
+    path_traversals: usize,
+}
+
+/// `slice(source, range)` is `&source[range]`.
+/// In debug builds, an invalid range panics with a message that tells
+/// which range is wrong, to catch the bugs in the computation of ranges.
+fn slice(source: &str, range: Range<usize>) -> &str {
+    debug_assert!(
+        range.start <= range.end && range.end <= source.len(),
+        "range {range:?} is out of the source of length {}", source.len()
+    );
+    debug_assert!(
+        source.is_char_boundary(range.start) && source.is_char_boundary(range.end),
+        "range {range:?} is not on a char boundary of {source:?}"
+    );
+    &source[range]
+}
+
+/// `trim_whitespace(source, range)` is the range of `source[range].trim()`
+fn trim_whitespace(source: &str, range: Range<usize>) -> Range<usize> {
+    let s = slice(source, range.clone());
+    let start = range.start + (s.len() - s.trim_start().len());
+    let end = range.end - (s.len() - s.trim_end().len());
+    start..end.max(start)
+}
+
+/// a piece of a text event, once reparsed by the [`WikiParser`]
+#[allow(clippy::large_enum_variant)]
+enum Chunk<'a> {
+    Text(Range<usize>),
+    Link(WikiLink<'a>),
 }
 
 
@@ -83,11 +238,41 @@ impl ParseError {
 impl<'a, 'b> WikiParser<'a, 'b> 
     where 'a: 'b
     {
+    /// parses exactly the `range` of `source`, with the default options.
+    ///
+    /// Panics if `range` is out of `source` or not on char boundaries
     pub fn new(source: &'a str, range: Range<usize>) -> Self {
+        Self::new_with(source, range, Arc::new(WikiOptions::default()))
+    }
+
+    /// parses the whole lines of `source` that `range` touches, with `options`.
+    ///
+    /// A wikilink doesn't span several lines, so every link that overlaps `range`
+    /// is parsed entirely. `range` is clamped to `source`, and doesn't need
+    /// to be on char boundaries
+    pub fn new_at_lines(source: &'a str, range: Range<usize>, options: WikiOptions) -> Self {
+        // a `\n` is never inside a multibyte char, so the bytes can be searched
+        let bytes = source.as_bytes();
+        let end = range.end.min(source.len());
+        let start = range.start.min(end);
+        let start = bytes[..start].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let end = bytes[end..].iter().position(|&b| b == b'\n').map_or(source.len(), |i| end + i);
+        Self::new_with(source, start..end, Arc::new(options))
+    }
+
+    fn new_with(source: &'a str, range: Range<usize>, options: Arc<WikiOptions>) -> Self {
         Self {
             source,
-            lexer: Lexer::new_at(&source[range.clone()], range.start).peekable(),
-            buffer: Vec::new().into_iter()
+            start: range.start,
+            end: range.end,
+            lexer: Lexer::with_separator(&source[range.clone()], range.start, options.separator.clone())
+                .with_parens(options.roam_block_refs)
+                .with_delimiters(options.delimiters.clone())
+                .peekable(),
+            buffer: Vec::new().into_iter(),
+            options,
+            stripped_chars: 0,
+            path_traversals: 0,
         }
     }
 
@@ -99,8 +284,17 @@ impl<'a, 'b> WikiParser<'a, 'b>
         };
         let mut end: usize = start.clone();
         loop {
+            let closing = self.at_closing();
             match self.lexer.peek() {
-                Some((Pipe, _))| Some((RRBra, _)) => break Ok(start..end),
+                Some((Pipe, _)) => break Ok(start..end),
+                Some((RRBra, _)) if closing => break Ok(start..end),
+                Some((LLBra, _)) if self.options.partial_input => {
+                    return Err(ParseError::ReParse(start..end))
+                }
+                Some((NewLine, _)) if self.options.newline_terminates_link => {
+                    let end = self.lexer.next().unwrap().1.end;
+                    return Err(ParseError::ReParse(start..end))
+                }
                 Some((_, _)) => {
                     end = self.lexer.next().unwrap().1.end;
                 }
@@ -109,6 +303,27 @@ impl<'a, 'b> WikiParser<'a, 'b>
         }
     }
 
+    /// `self.at_closing()` is true if the next token is a `]]` that closes the wikilink.
+    /// With `greedy_close`, it is only the case if there is no other `]]`
+    /// later on the line, before the next `[[`
+    fn at_closing(&mut self) -> bool {
+        let start = match self.lexer.peek() {
+            Some((RRBra, r)) => r.start,
+            _ => return false,
+        };
+        if self.options.escaped_brackets && self.source[..start].ends_with('\\') {
+            return false
+        }
+        if !self.options.greedy_close {
+            return true
+        }
+        let mut ahead = self.lexer.clone();
+        ahead.next();
+        !ahead
+            .take_while(|(t, _)| !matches!(t, NewLine | LLBra))
+            .any(|(t, _)| t == RRBra)
+    }
+
     /// in `link]]`, returns `link` and don't consume the `]]`
     fn parse_wikilink_alias(&mut self) -> Result<Range<usize>, ParseError>{
         let start : usize = match self.lexer.peek(){
@@ -117,8 +332,16 @@ impl<'a, 'b> WikiParser<'a, 'b>
         };
         let mut end: usize = start.clone();
         loop {
+            let closing = self.at_closing();
             match self.lexer.peek() {
-                Some((RRBra, _)) => return Ok(start..end),
+                Some((RRBra, _)) if closing => return Ok(start..end),
+                Some((LLBra, _)) if self.options.partial_input => {
+                    return Err(ParseError::ReParse(start..end))
+                }
+                Some((NewLine, _)) if self.options.newline_terminates_link => {
+                    let end = self.lexer.next().unwrap().1.end;
+                    return Err(ParseError::ReParse(start..end))
+                }
                 Some((_, _)) => {
                     end = self.lexer.next().unwrap().1.end;
                 }
@@ -130,104 +353,636 @@ impl<'a, 'b> WikiParser<'a, 'b>
     /// parse an entire wikilink, ie one of
     /// - `[[a shortcut url]]`
     /// - `[[a url|with some displayed content]]`
-    fn parse_wikilink(&mut self) -> Result<Vec<(Event<'a>, Range<usize>)>, ParseError> {
-        let tag_pos = self.lexer.next().unwrap().1;
-        let url_pos = self.parse_wikilink_first_field()
-            .map_err(|x| x.extend_before(tag_pos.clone()))?;
+    /// - `![[an embed]]`, if embeds are enabled
+    ///
+    /// The first `]]` closes the link, unless a `[` is still open inside it,
+    /// so `[[[x]]]` links to `[x]`, but `[[a]]]` links to `a` followed by `]`
+    fn parse_wikilink(&mut self) -> Result<WikiLink<'a>, ParseError> {
+        let mut tag_pos = self.lexer.next().unwrap().1;
+        let embed = self.is_embed(tag_pos.start);
+        if embed {
+            tag_pos.start -= 1;
+        }
 
-        let opening_tag = Event::Start(Tag::Link{
-                link_type: LinkType::Inline,
-                dest_url: self.source[url_pos.clone()].into(),
-                title: "wiki".into(),
-                id: "".into(),
-        });
+        let raw_url_pos = self.parse_wikilink_first_field()
+            .map_err(|x| x.extend_before(tag_pos.clone()))?;
+        let url_pos = self.trim(raw_url_pos.clone());
+        let raw_url = slice(self.source, raw_url_pos.clone());
+        let padded = raw_url.trim().len() != raw_url.len();
+        let url_pos = if self.options.unquote_targets { self.unquote(url_pos) } else { url_pos };
+        // in `[[Show::real-page]]`, `Show` is displayed
+        let (display_before, url_pos) = match &self.options.display_separator {
+            Some(sep) if !self.options.raw_targets => match slice(self.source, url_pos.clone()).find(&**sep) {
+                Some(i) => {
+                    let i = url_pos.start + i;
+                    (Some(self.trim(url_pos.start..i)), self.trim(i + sep.len()..url_pos.end))
+                }
+                None => (None, url_pos),
+            },
+            _ => (None, url_pos),
+        };
 
-        let closing_tag = Event::End(TagEnd::Link);
+        let (pipe_pos, alias_pos, close_pos) = match self.lexer.next() {
+            Some((RRBra, x)) => (None, None, x),
+            Some((Pipe, pipe_pos)) => {
+                if self.options.strict_pipes {
+                    if let Some((Pipe, x)) = self.lexer.peek() {
+                        let end = x.end;
+                        self.lexer.next();
+                        return Err(ParseError::ReParse(tag_pos.start..end))
+                    }
+                }
 
-        match self.lexer.next() {
-            Some((RRBra, x)) => {
-                Ok(vec![
-                    (opening_tag, tag_pos.start..x.end),
-                    (Event::Text(self.source[url_pos.clone()].into()), url_pos),
-                    (closing_tag, tag_pos.start..x.end),
-                ])
-            },
-            Some((Pipe, _)) => {
                 let alias_pos = self.parse_wikilink_alias()
                     .map_err(|x| x.extend_before(tag_pos.clone()))?;
 
-                let end = self.lexer.next().unwrap().1.end;
-                Ok(vec![
-                   (opening_tag, tag_pos.start..end),
-                    (Event::Text(self.source[alias_pos.clone()].into()), alias_pos),
-                   (closing_tag, tag_pos.start..end),
-                ])
+                (Some(pipe_pos), Some(self.trim(alias_pos)), self.lexer.next().unwrap().1)
             }
             _ => unreachable!()
+        };
+
+        // in an embed, the fields after the pipe are parameters,
+        // and only the alt text is displayed
+        let (embed_params, alt_pos) = match alias_pos.clone().filter(|_| embed) {
+            Some(r) => match EmbedParams::parse(self.source, r) {
+                Some((params, alt_pos)) => (Some(params), alt_pos),
+                None => (None, None),
+            },
+            None => (None, None),
+        };
+        let alias_pos = alias_pos.or(display_before);
+        let shown_alias = if embed { alt_pos } else { alias_pos.clone() };
+
+        let mode = self.options.display_mode;
+        // `[[a|]]` displays nothing by default, but a blank alias like in `[[a| ]]` is never kept
+        let empty_alias = mode == DisplayMode::AliasOrTarget
+            && pipe_pos.as_ref().is_some_and(|p| p.end == close_pos.start);
+        let display_pos = match &shown_alias {
+            Some(r) if !slice(self.source, r.clone()).trim().is_empty() || empty_alias => {
+                r.clone()
+            }
+            _ => {
+                let mut r = url_pos.clone();
+                if shown_alias.is_some() {
+                    // `[[Page | ]]` displays `Page`, even without `trim_spaces`
+                    r = trim_whitespace(self.source, r);
+                }
+                if self.options.folder_index.is_some() && r.len() > 1 && slice(self.source, r.clone()).ends_with('/') {
+                    r.end -= 1;
+                }
+                if mode == DisplayMode::LastSegment {
+                    r.start += slice(self.source, r.clone()).rfind('/').map_or(0, |i| i + 1);
+                }
+                // a link to the same page, like `[[#Section]]`, doesn't display the `#`
+                if slice(self.source, r.clone()).starts_with('#') {
+                    r.start += 1;
+                }
+                r
+            }
+        };
+        let url_pos = if self.options.raw_targets { raw_url_pos } else { url_pos };
+
+        let target: CowStr<'a> = slice(self.source, url_pos.clone()).into();
+        let target = if self.options.raw_targets { target } else { self.unescape(target) };
+        let target = match &self.options.strip_chars {
+            Some(chars) if !self.options.raw_targets && target.contains(&chars[..]) => {
+                let stripped: String = target.chars().filter(|c| !chars.contains(c)).collect();
+                self.stripped_chars += target.chars().count() - stripped.chars().count();
+                stripped.into()
+            }
+            _ => target,
+        };
+        #[cfg(feature = "unicode-normalization")]
+        let target = if self.options.nfc_targets && !self.options.raw_targets && !unicode_normalization::is_nfc(&target) {
+            use unicode_normalization::UnicodeNormalization;
+            target.nfc().collect::<String>().into()
+        } else {
+            target
+        };
+        let external = self.options.external_links && wikilink::has_scheme(&target);
+        if !external && wikilink::has_traversal(&target) {
+            self.path_traversals += 1;
+            if self.options.reject_path_traversal {
+                return Err(ParseError::ReParse(tag_pos.start..close_pos.end))
+            }
+        }
+        let image = embed && self.options.is_image(&target);
+        let link_type = WikiLinkType::classify(embed, image, &target, alias_pos.is_some());
+        // `[[^blockid]]` is a block of the same page, like `[[#^blockid]]`
+        let target = if target.starts_with('^') && !self.options.raw_targets {
+            format!("#{target}").into()
+        } else {
+            target
+        };
+        let target = match &self.options.folder_index {
+            Some(index) if !external && !self.options.raw_targets => {
+                let (page, fragment) = match target.find('#') {
+                    Some(i) => target.split_at(i),
+                    None => (&*target, ""),
+                };
+                if page.ends_with('/') {
+                    format!("{page}{index}{fragment}").into()
+                } else {
+                    target
+                }
+            }
+            _ => target,
+        };
+        let target = match &self.options.current_dir {
+            Some(dir) if !external && !self.options.raw_targets => {
+                match wikilink::resolve_relative(dir, &target) {
+                    Some(resolved) => resolved.into(),
+                    None => target,
+                }
+            }
+            _ => target,
+        };
+        let target = match target.split_once('#') {
+            Some((page, fragment))
+                if self.options.fragment_style == FragmentStyle::GithubSlug
+                    && !external
+                    && !self.options.raw_targets
+                    && !fragment.starts_with('^') =>
+            {
+                format!("{page}#{}", wikilink::github_slug(fragment)).into()
+            }
+            _ => target,
+        };
+        let target = match &self.options.transform {
+            Some(t) if !external && !self.options.raw_targets => t.transform(target),
+            _ => target,
+        };
+        let target = match self.options.space_replacement {
+            Some(c) if !external && !self.options.raw_targets && target.contains(' ') => {
+                target.replace(' ', c.encode_utf8(&mut [0; 4])).into()
+            }
+            _ => target,
+        };
+        let target = match &self.options.allowed_chars {
+            Some(allowed) if !target.chars().all(|c| allowed(c)) => {
+                if !self.options.sanitize_targets || self.options.raw_targets {
+                    return Err(ParseError::ReParse(tag_pos.start..close_pos.end))
+                }
+                target.chars().filter(|&c| allowed(c)).collect::<String>().into()
+            }
+            _ => target,
+        };
+        let (prefix, suffix) = (&self.options.target_prefix, &self.options.target_suffix);
+        let target = if (prefix.is_empty() && suffix.is_empty())
+            || external
+            || self.options.raw_targets
+            || target.starts_with('#')
+        {
+            target
+        } else {
+            // the suffix is an extension of the page, so it goes before the fragment
+            let (page, fragment) = match target.find('#') {
+                Some(i) => target.split_at(i),
+                None => (&*target, ""),
+            };
+            format!("{prefix}{page}{suffix}{fragment}").into()
+        };
+
+        let display = self.unescape(slice(self.source, display_pos.clone()).into());
+        let display = if self.options.title_case_display && alias_pos.is_none() {
+            wikilink::title_case(&display).into()
+        } else {
+            display
+        };
+
+        let id = if self.options.generate_ids {
+            format!("wikilink-{}", wikilink::slug(&target)).into()
+        } else {
+            "".into()
+        };
+
+        Ok(WikiLink {
+            target,
+            display,
+            id,
+            range: tag_pos.start..close_pos.end,
+            open_delim_range: tag_pos,
+            close_delim_range: close_pos,
+            parts: self.split_target(url_pos.clone()),
+            target_range: url_pos,
+            padded,
+            pipe_range: pipe_pos,
+            alias_range: alias_pos,
+            display_range: display_pos,
+            external,
+            link_type,
+            embed_params,
+            kind: None,
+            broken: false,
+            block: false,
+        })
+    }
+
+    /// parses a roam block ref like `((uid))`, where the uid is only made of
+    /// alphanumeric characters, `-` and `_`.
+    /// Otherwise, returns the range to parse as text
+    fn parse_roam_block_ref(&mut self) -> Result<WikiLink<'a>, Range<usize>> {
+        let open = self.lexer.next().unwrap().1;
+        let mut end = open.end;
+        let close = loop {
+            match self.lexer.peek() {
+                Some((RRPar, _)) => break self.lexer.next().unwrap().1,
+                Some((Word, _)) => end = self.lexer.next().unwrap().1.end,
+                _ => return Err(open.start..end),
+            }
+        };
+        let uid_range = open.end..close.start;
+        let uid = slice(self.source, uid_range.clone());
+        if uid.is_empty() || !uid.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(open.start..close.end)
+        }
+
+        Ok(WikiLink {
+            target: uid.into(),
+            display: uid.into(),
+            id: "".into(),
+            range: open.start..close.end,
+            open_delim_range: open,
+            close_delim_range: close,
+            parts: TargetParts {
+                page: uid_range.start..uid_range.start,
+                fragment: None,
+                block: Some(uid_range.clone()),
+            },
+            target_range: uid_range.clone(),
+            padded: false,
+            pipe_range: None,
+            alias_range: None,
+            display_range: uid_range,
+            external: false,
+            link_type: WikiLinkType::RoamBlockRef,
+            embed_params: None,
+            kind: None,
+            broken: false,
+            block: false,
+        })
+    }
+
+    /// parses a link like `[Page]`, if it is not followed by `(`, `[` or `:`,
+    /// nor preceded by `]`, and is not a footnote like `[^1]`.
+    /// Otherwise, returns the range to parse as text
+    fn parse_single_bracket(&mut self) -> Result<WikiLink<'a>, Range<usize>> {
+        let open = self.lexer.next().unwrap().1;
+        let mut end = open.end;
+        let close = loop {
+            match self.lexer.peek() {
+                Some((RBra, _)) => break self.lexer.next().unwrap().1,
+                Some((Word, _)) => end = self.lexer.next().unwrap().1.end,
+                _ => return Err(open.start..end),
+            }
+        };
+        let s = slice(self.source, open.end..close.start);
+        let start = open.end + s.len() - s.trim_start().len();
+        let target_pos = start..start + s.trim().len();
+        if target_pos.is_empty()
+            || self.source[close.end..].starts_with(['(', '[', ':'])
+            || self.source[..open.start].ends_with(']')
+            || slice(self.source, target_pos.clone()).starts_with('^')
+        {
+            return Err(open.start..close.end)
+        }
+        let target = slice(self.source, target_pos.clone());
+        let padded = target_pos != (open.end..close.start);
+
+        Ok(WikiLink {
+            target: target.into(),
+            display: target.into(),
+            id: "".into(),
+            range: open.start..close.end,
+            open_delim_range: open,
+            close_delim_range: close,
+            parts: TargetParts { page: target_pos.clone(), fragment: None, block: None },
+            padded,
+            target_range: target_pos.clone(),
+            pipe_range: None,
+            alias_range: None,
+            display_range: target_pos,
+            external: false,
+            link_type: WikiLinkType::Shortcut,
+            embed_params: None,
+            kind: None,
+            broken: false,
+            block: false,
+        })
+    }
+
+    /// parses a link between the `i`th extra delimiters, like `{{target|alias}}`.
+    /// Otherwise, returns the range to parse as text
+    fn parse_delimited(&mut self, i: usize) -> Result<WikiLink<'a>, Range<usize>> {
+        let open = self.lexer.next().unwrap().1;
+        let mut end = open.end;
+        let mut pipe = None;
+        let close = loop {
+            match self.lexer.peek() {
+                Some(&(Close(j), _)) if j == i => break self.lexer.next().unwrap().1,
+                Some((Pipe, _)) if pipe.is_none() => {
+                    let r = self.lexer.next().unwrap().1;
+                    end = r.end;
+                    pipe = Some(r);
+                }
+                Some((NewLine | LLBra | Open(_), _)) | None => return Err(open.start..end),
+                Some(_) => end = self.lexer.next().unwrap().1.end,
+            }
+        };
+        let trim = |r: Range<usize>| {
+            let s = slice(self.source, r.clone());
+            let start = r.start + s.len() - s.trim_start().len();
+            start..start + s.trim().len()
+        };
+        let raw_target_pos = open.end..pipe.as_ref().map_or(close.start, |p| p.start);
+        let target_pos = trim(raw_target_pos.clone());
+        let alias_pos = pipe.as_ref().map(|p| trim(p.end..close.start));
+        if target_pos.is_empty() {
+            return Err(open.start..close.end)
+        }
+        let display_pos = alias_pos.clone().unwrap_or(target_pos.clone());
+        let target = slice(self.source, target_pos.clone());
+
+        Ok(WikiLink {
+            target: target.into(),
+            display: slice(self.source, display_pos.clone()).into(),
+            id: "".into(),
+            range: open.start..close.end,
+            open_delim_range: open,
+            close_delim_range: close,
+            parts: TargetParts { page: target_pos.clone(), fragment: None, block: None },
+            padded: target_pos != raw_target_pos,
+            pipe_range: pipe,
+            target_range: target_pos,
+            link_type: if alias_pos.is_some() { WikiLinkType::Aliased } else { WikiLinkType::Shortcut },
+            alias_range: alias_pos,
+            display_range: display_pos,
+            external: false,
+            embed_params: None,
+            kind: self.options.delimiters.as_ref().map(|d| d[i].kind.clone()),
+            broken: false,
+            block: false,
+        })
+    }
+
+    /// splits the target at `range` into its page, fragment and block,
+    /// according to the options
+    fn split_target(&self, range: Range<usize>) -> TargetParts {
+        let target = slice(self.source, range.clone());
+        let at = |i: usize| range.start + i;
+        let (split_fragments, split_blocks) = (self.options.split_fragments, self.options.split_block_refs);
+
+        if split_blocks && target.starts_with('^') {
+            return TargetParts { page: at(0)..at(0), fragment: None, block: Some(at(1)..range.end) }
+        }
+        let page_end = match (target.find('#'), target.find("#^")) {
+            (_, Some(i)) if split_blocks => i,
+            (Some(i), _) if split_fragments => i,
+            _ => return TargetParts { page: range, fragment: None, block: None },
+        };
+        let page = at(0)..at(page_end);
+        if split_blocks && target[page_end..].starts_with("#^") {
+            TargetParts { page, fragment: None, block: Some(at(page_end + 2)..range.end) }
+        } else {
+            TargetParts { page, fragment: Some(at(page_end + 1)..range.end), block: None }
+        }
+    }
+
+    /// with `escaped_brackets`, removes the backslash of each `\]]` in `s`
+    fn unescape(&self, s: CowStr<'a>) -> CowStr<'a> {
+        if self.options.escaped_brackets && s.contains("\\]]") {
+            s.replace("\\]]", "]]").into()
+        } else {
+            s
+        }
+    }
+
+    /// `self.is_embed(pos)` is true if the `[[` at `pos` is the start of an embed,
+    /// ie embeds are enabled and it follows a `!`
+    fn is_embed(&self, pos: usize) -> bool {
+        self.options.embeds && pos > self.start && self.source[..pos].ends_with('!')
+    }
+
+    /// if `trim_spaces` is enabled, shrinks `range` so that
+    /// it doesn't start or end with whitespace
+    fn trim(&self, range: Range<usize>) -> Range<usize> {
+        if !self.options.trim_spaces {
+            return range
+        }
+        trim_whitespace(self.source, range)
+    }
+
+    /// shrinks `range` so that it doesn't include the matching quotes around it
+    fn unquote(&self, range: Range<usize>) -> Range<usize> {
+        let s = slice(self.source, range.clone());
+        let quoted = |q| s.len() >= 2 && s.starts_with(q) && s.ends_with(q);
+        if quoted('"') || quoted('\'') {
+            range.start + 1..range.end - 1
+        } else {
+            range
         }
     }
 
-    // parse a text until the first `[[` (start of wikilink) is encountered.
-    // don't consume the `[[`
-    fn parse_text(&mut self) -> Range<usize> {
-        let start = self.lexer.peek().unwrap().1.start.clone();
-        let mut end = start.clone();
+    // parse a text until the first `[[` (start of wikilink) is encountered,
+    // or `((` with roam block refs. Don't consume the `[[`.
+    // returns `None` if there is no token left
+    fn parse_text(&mut self) -> Option<Range<usize>> {
+        let start = self.lexer.peek()?.1.start;
+        let mut end = start;
         loop {
             match self.lexer.peek() {
-                Some((LLBra, _)) | None => return start..end,
+                Some((LLBra, x)) => {
+                    // the `!` of an embed is not part of the text
+                    let pos = x.start;
+                    if self.is_embed(pos) {
+                        end -= 1;
+                    }
+                    return Some(start..end)
+                },
+                Some((LLPar | Open(_), _)) | None => return Some(start..end),
+                Some((LBra, _)) if self.options.single_bracket_links => return Some(start..end),
                 Some((_, _)) => {
                     end = self.lexer.next().unwrap().1.end;
                 }
             }
         }
     }
-}
 
-impl<'a, 'b> Iterator for WikiParser<'a, 'b> where 'a: 'b {
-    type Item = (Event<'a>, Range<usize>);
-    fn next(&mut self) -> Option<Self::Item> {
-        // returns the last group of events that was created
-        if let Some((e, range)) = self.buffer.next() {
-            return Some((e, range))
-        };
+    /// the range that is not parsed yet, if any
+    fn rest(&mut self) -> Option<Range<usize>> {
+        let start = self.lexer.peek()?.1.start;
+        Some(start..self.end)
+    }
 
+    /// returns either the next wikilink, either the text
+    /// up to the next wikilink
+    fn next_chunk(&mut self) -> Option<Chunk<'a>> {
         // suppress useless newlines
         while let Some((Token::NewLine, _)) = self.lexer.peek() {
             self.lexer.next();
         };
 
         match self.lexer.peek()? {
-            (LLBra, x) => {
-                let _start = x.start.clone();
+            (LLBra, _) => {
                 match self.parse_wikilink() {
-                    Ok(b) => {
-                        self.buffer = b.into_iter();
-                        self.buffer.next()
-                    },
+                    Ok(l) => Some(Chunk::Link(l)),
                     Err(e) => {
                         let r = match e {
                             ParseError::ReParse(r) => r,
                             _ => unreachable!(),
                         };
-                        Some((Event::Text(self.source[r.clone()].into()), r))
+                        Some(Chunk::Text(r))
                     }
                 }
             },
+            (LLPar, _) => {
+                match self.parse_roam_block_ref() {
+                    Ok(l) => Some(Chunk::Link(l)),
+                    Err(r) => Some(Chunk::Text(r)),
+                }
+            },
+            &(Open(i), _) => {
+                match self.parse_delimited(i) {
+                    Ok(l) => Some(Chunk::Link(l)),
+                    Err(r) => Some(Chunk::Text(r)),
+                }
+            },
+            (LBra, _) if self.options.single_bracket_links => {
+                match self.parse_single_bracket() {
+                    Ok(l) => Some(Chunk::Link(l)),
+                    Err(r) => Some(Chunk::Text(r)),
+                }
+            },
             _ => {
-                let r = self.parse_text();
-                Some((Event::Text(self.source[r.clone()].into()), r))
+                let r = self.parse_text()?;
+                if r.is_empty() {
+                    // the text was only the `!` of an embed
+                    return self.next_chunk()
+                }
+                Some(Chunk::Text(r))
             }
         }
     }
 }
 
+impl<'a, 'b> Iterator for WikiParser<'a, 'b> where 'a: 'b {
+    type Item = (Event<'a>, Range<usize>);
+    fn next(&mut self) -> Option<Self::Item> {
+        // returns the last group of events that was created
+        if let Some((e, range)) = self.buffer.next() {
+            return Some((e, range))
+        };
+
+        match self.next_chunk()? {
+            Chunk::Link(l) => {
+                self.buffer = l.into_output_events(self.source, 0, &self.options);
+                self.buffer.next()
+            },
+            Chunk::Text(r) => Some((Event::Text(slice(self.source, r.clone()).into()), r)),
+        }
+    }
+}
+
+/// Parses `source` as a single wikilink, like `[[target|alias]]`,
+/// without any markdown processing.
+///
+/// Surrounding whitespace is ignored. Returns `None` if `source` is not
+/// exactly one wikilink, or if its target is empty.
+pub fn parse_single_wikilink(source: &str) -> Option<WikiLink<'_>> {
+    let start = source.len() - source.trim_start().len();
+    let end = start + source.trim().len();
+    let mut parser = WikiParser::new(source, start..end);
+    match (parser.next_chunk(), parser.next_chunk()) {
+        (Some(Chunk::Link(l)), None) if !l.target.is_empty() => Some(l),
+        _ => None,
+    }
+}
+
+/// The events of pulldown-cmark, with the consecutive text events merged
+/// and the empty ones dropped, as they are before the wikilinks are parsed.
+///
+/// A wikilink is only found if it is inside a single of these text events,
+/// so this helps to understand why one is not detected.
+///
+/// ```
+/// use pulldown_cmark_wikilink::{joined_events, Event, Options};
+///
+/// let events: Vec<_> = joined_events("see [[a]]", Options::empty()).collect();
+/// assert_eq!(events[1], (Event::Text("see [[a]]".into()), 0..9));
+/// ```
+pub fn joined_events(source: &str, options: Options) -> impl Iterator<Item = (Event<'_>, Range<usize>)> {
+    TextJoiner::new_ext(source, options, true)
+}
+
+/// `is_valid_wikilink_target(target)` is true if `[[target]]` is a wikilink
+/// that links to `target`, ie `target` is not blank and contains
+/// neither `[[`, `]]`, `|` nor a newline.
+pub fn is_valid_wikilink_target(target: &str) -> bool {
+    !target.trim().is_empty()
+        && Lexer::new_at(target, 0).all(|(t, _)| matches!(t, Word | LBra | RBra))
+}
+
+/// `contains_wikilink(source)` is true if `source` contains a closed wikilink
+/// with a non blank target, like `[[a]]`, to skip the files without links
+/// before parsing them.
+///
+/// This is a cheap check that doesn't parse the markdown
+/// and doesn't allocate: a wikilink inside a code block or a code span counts,
+/// so it can be true for a file where [`ParserOffsetIter`] finds no link.
+pub fn contains_wikilink(source: &str) -> bool {
+    // `Some(blank)` inside `[[`, where `blank` is true while the target is blank
+    let mut target_blank = None;
+    for (t, r) in Lexer::new_at(source, 0) {
+        target_blank = match (t, target_blank) {
+            (LLBra, _) => Some(true),
+            (NewLine, _) => None,
+            (RRBra, Some(false)) => return true,
+            (RRBra, _) => None,
+            (Word, Some(true)) => Some(source[r].trim().is_empty()),
+            (Pipe, Some(true)) => None,
+            (_, blank) => blank,
+        }
+    }
+    false
+}
+
+/// a callback called on each wikilink, see [`ParserOffsetIter::on_wikilink`]
+type LinkCallback<'a, 'b> = Box<dyn FnMut(&WikiLink<'a>) + Send + 'b>;
+
+/// An iterator over the `(Event, Range)` pairs of a markdown document,
+/// where the wikilinks are parsed inside the text events.
+///
+/// A wikilink can't span several lines: a soft or hard line break
+/// splits the text, and such a wikilink is kept as text.
 pub struct ParserOffsetIter<'a, 'b> {
     source: &'a str,
+    options: Options,
     wikilinks: bool,
     events: TextJoiner<'a, 'b>,
+    /// the parser of the text event that is being split into chunks
+    text: Option<WikiParser<'a, 'a>>,
+    /// the chunk that was parsed ahead, to know if a link is alone in its text
+    next_chunk: Option<Chunk<'a>>,
     buffer: vec::IntoIter<(Event<'a>, Range<usize>)>,
+    /// the event returned by [`peek`](Self::peek), that is the next one returned
+    peeked: Option<(Event<'a>, Range<usize>)>,
     inside_metadata: bool,
     inside_codeblock: bool,
+    /// the source is only whitespace, so there is no event at all
+    blank: bool,
+    /// the last event from `events` was the start of a paragraph
+    after_paragraph_start: bool,
+    wiki_options: Arc<WikiOptions>,
+    base_offset: usize,
+    stats: ParseStats,
+    on_wikilink: Option<LinkCallback<'a, 'b>>,
+}
+
+/// an element of the stream, before wikilinks are expanded into events
+#[allow(clippy::large_enum_variant)]
+enum Item<'a> {
+    Event(Event<'a>, Range<usize>),
+    Link(WikiLink<'a>),
 }
 
 impl<'a, 'b> ParserOffsetIter<'a, 'b> {
@@ -235,98 +990,2056 @@ impl<'a, 'b> ParserOffsetIter<'a, 'b> {
     pub fn new_ext(source: &'a str, options: Options, wikilinks: bool) -> Self {
         Self {
             source,
+            options,
             wikilinks,
-            events: TextJoiner::new_ext(source, options),
+            events: TextJoiner::new_ext(source, options, wikilinks),
+            text: None,
+            next_chunk: None,
             buffer: Vec::new().into_iter(),
+            peeked: None,
             inside_metadata: false,
             inside_codeblock: false,
+            blank: is_blank(source),
+            after_paragraph_start: false,
+            wiki_options: Arc::new(WikiOptions::default()),
+            base_offset: 0,
+            stats: ParseStats::default(),
+            on_wikilink: None,
         }
     }
 
-    // /// Consumes the event iterator and produces an iterator that produces
-    // /// `(Event, Range)` pairs, where the `Range` value maps to the corresponding
-    // /// range in the markdown source.
-    // pub fn into_offset_iter(self) -> OffsetIter<'a, 'b> {
-    //     OffsetIter {
-    //         source: self.source,
-    //         wikilinks: self.wikilinks,
-    //         events: self.events,
-    //         buffer: self.buffer,
-    //         inside_metadata: self.inside_metadata,
-    //         inside_codeblock: self.inside_codeblock
-    //     }
-    // }
-}
+    /// Same as [`new_ext`](Self::new_ext), but every emitted range is shifted by
+    /// `base_offset`.
+    ///
+    /// This is useful when `source` is a fragment of a larger file,
+    /// starting at byte `base_offset` of that file.
+    pub fn new_ext_at(source: &'a str, base_offset: usize, options: Options, wikilinks: bool) -> Self {
+        Self {
+            base_offset,
+            ..Self::new_ext(source, options, wikilinks)
+        }
+    }
 
+    /// Creates a new event iterator for a markdown string with given options,
+    /// where wikilinks are parsed according to `wiki_options`
+    pub fn new_with(source: &'a str, options: Options, wiki_options: WikiOptions) -> Self {
+        Self::new_shared(source, options, Arc::new(wiki_options))
+    }
 
-impl<'a, 'b> Iterator for ParserOffsetIter<'a, 'b> {
-    type Item = (Event<'a>, Range<usize>);
-    fn next(&mut self) -> Option<Self::Item> {
+    /// same as [`new_with`](Self::new_with), with options that are shared with other parsers
+    pub(crate) fn new_shared(source: &'a str, options: Options, wiki_options: Arc<WikiOptions>) -> Self {
+        let mut parser = Self {
+            wiki_options,
+            ..Self::new_ext(source, options, true)
+        };
+        parser.events.bridge_html = parser.wiki_options.bridge_inline_html;
+        parser
+    }
+
+    /// shifts every emitted range, including those of the wikilinks, by `delta` more bytes,
+    /// like [`new_ext_at`](Self::new_ext_at). The events that were peeked are not shifted
+    pub fn offset_by(mut self, delta: usize) -> Self {
+        self.base_offset += delta;
+        self
+    }
+
+    /// calls `f` on every wikilink, in the order of the source,
+    /// when it is produced by the iterator
+    pub fn on_wikilink(mut self, f: impl FnMut(&WikiLink<'a>) + Send + 'b) -> Self {
+        self.on_wikilink = Some(Box::new(f));
+        self
+    }
+
+    /// the next `(Event, Range)` pair, without consuming it.
+    ///
+    /// A wikilink is parsed, and counted in the [stats](Self::stats),
+    /// when its first event is peeked
+    pub fn peek(&mut self) -> Option<&(Event<'a>, Range<usize>)> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next()?);
+        }
+        self.peeked.as_ref()
+    }
+
+    /// Starts again with a new `source`, keeping all the options
+    /// and the base offset.
+    ///
+    /// The events of the previous source that were not consumed are dropped.
+    pub fn reset(&mut self, source: &'a str) {
+        self.source = source;
+        self.events = TextJoiner::new_ext(source, self.options, self.wikilinks);
+        self.events.bridge_html = self.wiki_options.bridge_inline_html;
+        self.text = None;
+        self.next_chunk = None;
+        self.buffer = Vec::new().into_iter();
+        self.peeked = None;
+        self.inside_metadata = false;
+        self.inside_codeblock = false;
+        self.blank = is_blank(source);
+        self.after_paragraph_start = false;
+        self.stats = ParseStats::default();
+    }
+
+    /// what was parsed so far
+    pub fn stats(&self) -> ParseStats {
+        self.stats
+    }
+
+    /// Consumes the event iterator and produces an iterator over the wikilinks
+    /// of the document, with the position of each of their parts.
+    pub fn wikilinks(self) -> WikiLinks<'a, 'b> {
+        WikiLinks { parser: self }
+    }
+
+    /// Same as [`wikilinks`](Self::wikilinks), but each wikilink comes with the index
+    /// of the paragraph or heading it is in, counted from 0 in the document
+    pub fn wikilinks_with_block(self) -> WikiLinksWithBlock<'a, 'b> {
+        WikiLinksWithBlock { parser: self, blocks: 0 }
+    }
+
+    /// Consumes the event iterator and produces an iterator over all the events
+    /// except the wikilinks, to extract the prose of the document.
+    pub fn without_wikilinks(self) -> WithoutWikiLinks<'a, 'b> {
+        WithoutWikiLinks { parser: self }
+    }
+
+    /// Consumes the event iterator and produces an iterator where each wikilink
+    /// is a single [`CoalescedEvent::WikiLink`], instead of its start, text and end events
+    pub fn coalesce_wikilinks(self) -> CoalescedWikiLinks<'a, 'b> {
+        CoalescedWikiLinks { parser: self }
+    }
+
+    /// Consumes the event iterator and produces an iterator where each transclusion
+    /// like `![[note]]`, alone in its paragraph, is replaced by the events
+    /// of the markdown that `resolver` returns for its target.
+    ///
+    /// The content is parsed with the same options, and its transclusions are replaced too,
+    /// up to `max_depth` nested notes. A note that is already being inlined,
+    /// a note that `resolver` doesn't find, or a transclusion inside a paragraph
+    /// is kept as a link. The events of a note have the range of the transclusion
+    /// in this document.
+    ///
+    /// Transclusions are only parsed with [`WikiOptions::embeds`].
+    /// The [`WikiOptions::max_wikilinks`] limit is shared by the document and all the notes
+    /// it includes, so that nested notes can't emit more links than the limit.
+    ///
+    /// The notes must outlive the events, because the events borrow them:
+    /// the version of pulldown-cmark used here has no `Event::into_static`
+    /// to make them owned. To read the notes on demand, keep them in a store
+    /// that outlives the events, like a map filled beforehand or an arena.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use pulldown_cmark_wikilink::{Event, Options, ParserOffsetIter, WikiOptions};
+    ///
+    /// let notes = HashMap::from([("note".to_string(), "the *note*".to_string())]);
+    /// let wiki_options = WikiOptions::new().embeds(true);
+    /// let texts: Vec<_> = ParserOffsetIter::new_with("![[note]]", Options::empty(), wiki_options)
+    ///     .transclude(|target| notes.get(target).map(String::as_str), 4)
+    ///     .filter_map(|(e, _)| match e {
+    ///         Event::Text(t) => Some(t),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(texts, ["the ".into(), "note".into()]);
+    /// ```
+    pub fn transclude<F: Fn(&str) -> Option<&'a str>>(self, resolver: F, max_depth: usize) -> Transclusions<'a, 'b, F> {
+        Transclusions::new(self, resolver, max_depth)
+    }
+
+    /// Consumes the event iterator and produces an iterator that also tells,
+    /// for each event, whether it is part of a metadata block or a code block.
+    pub fn with_context(self) -> WithContext<'a, 'b> {
+        WithContext { parser: self }
+    }
+
+    /// Consumes the event iterator and produces an iterator that also gives,
+    /// for each event, the part of the source it comes from.
+    pub fn with_source(self) -> WithSource<'a, 'b> {
+        WithSource { parser: self }
+    }
+
+    /// Consumes the event iterator and produces an iterator that also gives,
+    /// for each event, the line and column where it starts and ends.
+    pub fn with_positions(self) -> WithPositions<'a, 'b> {
+        WithPositions {
+            index: LineIndex::new(self.source),
+            parser: self,
+        }
+    }
+
+    /// Consumes the event iterator and produces an iterator of events
+    /// where the wikilinks are kept as in the source, to write markdown back.
+    pub fn to_cmark(self) -> ToCmark<'a, 'b> {
+        ToCmark { parser: self }
+    }
+
+    /// Consumes the event iterator and produces an iterator of events
+    /// where the wikilinks are written as standard markdown links, like `[alias](target)`,
+    /// to export a document to tools that don't know wikilinks.
+    ///
+    /// The target is written after all the options are applied, like the
+    /// [`transform`](WikiOptions::transform)
+    pub fn to_standard_markdown(self) -> ToStandardMarkdown<'a, 'b> {
+        ToStandardMarkdown { parser: self }
+    }
+
+    /// the single text event that `link` is emitted as, if it is not emitted as a link,
+    /// see [`WikiOptions::links_as_text`] and [`WikiOptions::passthrough_wikilinks`]
+    fn link_as_text(&self, link: &WikiLink<'a>) -> Option<(Event<'a>, Range<usize>)> {
+        link.as_text(self.source, self.base_offset, &self.wiki_options)
+    }
+
+    /// the events that `link` is emitted as, according to the options
+    fn link_events(&self, link: WikiLink<'a>) -> vec::IntoIter<(Event<'a>, Range<usize>)> {
+        link.into_output_events(self.source, self.base_offset, &self.wiki_options)
+    }
+
+    /// returns the next event, or the next wikilink as a whole,
+    /// with ranges shifted by `base_offset`
+    fn next_item(&mut self) -> Option<Item<'a>> {
+        if self.blank {
+            return None
+        }
+        let offset = self.base_offset;
+        Some(match self.parse_item()? {
+            Item::Event(e, r) => Item::Event(e, r.start + offset..r.end + offset),
+            Item::Link(l) => {
+                let l = l.shifted(offset);
+                let processed = match &self.wiki_options.processor {
+                    Some(p) => p.process(l),
+                    None => ProcessedLink::Link(l),
+                };
+                let l = match processed {
+                    ProcessedLink::Link(l) => l,
+                    ProcessedLink::Broken(l) => WikiLink { broken: true, ..l },
+                    ProcessedLink::Drop(l) => return Some(Item::Event(Event::Text(l.display), l.display_range)),
+                };
+                self.stats.wikilinks += 1;
+                if let Some(f) = &mut self.on_wikilink {
+                    f(&l);
+                }
+                Item::Link(l)
+            }
+        })
+    }
+
+    /// `self.may_contain_link(text)` is false if `text` can't contain any wikilink
+    fn may_contain_link(&self, text: &str) -> bool {
+        text.contains("[[")
+            || (self.wiki_options.roam_block_refs && text.contains("(("))
+            || (self.wiki_options.single_bracket_links && text.contains('['))
+            || self.wiki_options.delimiters.iter().flat_map(|d| d.iter()).any(|d| text.contains(&*d.open))
+    }
+
+    fn parse_item(&mut self) -> Option<Item<'a>> {
         if !self.wikilinks {
-            return Some(self.events.next()?)
+            let (e, r) = self.events.next()?;
+            return Some(Item::Event(e, r))
         }
 
-        if let Some(x) = self.buffer.next() {
-            return Some(x)
+        if let Some(c) = self.pending_chunk() {
+            return Some(self.chunk_to_item(c))
         }
 
-        match self.events.next()? {
+        let next = self.events.next()?;
+        let after_paragraph_start = core::mem::replace(
+            &mut self.after_paragraph_start,
+            matches!(next.0, Event::Start(Tag::Paragraph))
+        );
+
+        let (e, r) = match next {
             (Event::End(TagEnd::MetadataBlock(k)), r) if self.inside_metadata => {
                 self.inside_metadata = false;
-                Some((Event::End(TagEnd::MetadataBlock(k)), r))
+                (Event::End(TagEnd::MetadataBlock(k)), r)
             },
             (Event::End(TagEnd::CodeBlock), r) if self.inside_codeblock => {
                 self.inside_codeblock = false;
-                Some((Event::End(TagEnd::CodeBlock), r))
+                (Event::End(TagEnd::CodeBlock), r)
             },
             (Event::Text(x), r) if self.inside_metadata || self.inside_codeblock => {
-                Some((Event::Text(x), r))
+                (Event::Text(x), r)
             },
             (Event::Start(Tag::MetadataBlock(k)), r) => {
                 self.inside_metadata = true;
-                Some((Event::Start(Tag::MetadataBlock(k)), r))
+                (Event::Start(Tag::MetadataBlock(k)), r)
             },
             (Event::Start(Tag::CodeBlock(k)), r) => {
                 self.inside_codeblock = true;
-                Some((Event::Start(Tag::CodeBlock(k)), r))
+                (Event::Start(Tag::CodeBlock(k)), r)
+            },
+            (Event::Text(x), r) if !self.may_contain_link(&x) => {
+                // no need to parse a text without any link
+                (Event::Text(x), r)
+            },
+            (Event::Text(x), r) if self.limit_reached() => {
+                self.record_limit(r.clone());
+                (Event::Text(x), r)
             },
             (Event::Text(_), range) => {
-                self.buffer = WikiParser::new(self.source, range)
-                    .collect::<Vec<_>>()
-                    .into_iter();
+                // the chunks are parsed one at a time, so that a huge paragraph
+                // is never entirely buffered
+                let mut parser = WikiParser::new_with(self.source, range, self.wiki_options.clone());
+                let mut c = parser.next_chunk().expect("an empty text should not be possible here");
+                let next_chunk = parser.next_chunk();
+                self.stats.stripped_chars += core::mem::take(&mut parser.stripped_chars);
+                self.stats.path_traversals += core::mem::take(&mut parser.path_traversals);
+
+                // a wikilink that is the only content of its paragraph
+                if let (Chunk::Link(l), None) = (&mut c, &next_chunk) {
+                    l.block = after_paragraph_start
+                        && matches!(self.events.peek(), Some((Event::End(TagEnd::Paragraph), _)));
+                }
+                self.next_chunk = next_chunk;
+                self.text = Some(parser);
 
-                Some(self.buffer.next().expect("an empty text should not be possible here"))
+                return Some(self.chunk_to_item(c))
             },
-            (other, r) => return Some((other, r))
-        }
+            (other, r) => (other, r)
+        };
+        Some(Item::Event(e, r))
     }
-}
 
+    /// `true` once [`WikiOptions::max_wikilinks`] were found,
+    /// and the rest of the document is not parsed anymore
+    fn limit_reached(&self) -> bool {
+        self.wiki_options.max_wikilinks.is_some_and(|max| self.stats.wikilinks >= max)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// the next chunk of the text event that is being parsed, if any
+    fn pending_chunk(&mut self) -> Option<Chunk<'a>> {
+        if self.limit_reached() {
+            return self.unparsed_rest()
+        }
+        if let Some(c) = self.next_chunk.take() {
+            return Some(c)
+        }
+        let parser = self.text.as_mut()?;
+        let c = parser.next_chunk();
+        self.stats.stripped_chars += core::mem::take(&mut parser.stripped_chars);
+        self.stats.path_traversals += core::mem::take(&mut parser.path_traversals);
+        if c.is_none() {
+            self.text = None;
+        }
+        c
+    }
+
+    /// the rest of the text event that is being parsed, as a single text chunk,
+    /// once the limit of wikilinks is reached
+    fn unparsed_rest(&mut self) -> Option<Chunk<'a>> {
+        let next = self.next_chunk.take().map(|c| match c {
+            Chunk::Text(r) => r,
+            Chunk::Link(l) => l.range,
+        });
+        let rest = self.text.take().and_then(|mut p| p.rest());
+        let range = match (next, rest) {
+            (Some(next), Some(rest)) => next.start..rest.end,
+            (next, rest) => next.or(rest)?,
+        };
+        self.record_limit(range.clone());
+        Some(Chunk::Text(range))
+    }
+
+    /// sets [`ParseStats::limit_reached_at`] to the start of `range`
+    /// if it is the first text after the limit that has a wikilink.
+    /// The text is only parsed up to its first wikilink, and only until one is found
+    fn record_limit(&mut self, range: Range<usize>) {
+        if self.stats.limit_reached_at.is_some() || !self.may_contain_link(slice(self.source, range.clone())) {
+            return
+        }
+        let start = range.start;
+        let mut parser = WikiParser::new_with(self.source, range, self.wiki_options.clone());
+        if core::iter::from_fn(|| parser.next_chunk()).any(|c| matches!(c, Chunk::Link(_))) {
+            self.stats.limit_reached_at = Some(start + self.base_offset);
+        }
+    }
+
+    fn chunk_to_item(&self, chunk: Chunk<'a>) -> Item<'a> {
+        match chunk {
+            Chunk::Text(r) => Item::Event(Event::Text(slice(self.source, r.clone()).into()), r),
+            Chunk::Link(l) => Item::Link(l),
+        }
+    }
+}
+
+
+impl<'a, 'b> Iterator for ParserOffsetIter<'a, 'b> {
+    type Item = (Event<'a>, Range<usize>);
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(x) = self.peeked.take() {
+            return Some(x)
+        }
+        if let Some(x) = self.buffer.next() {
+            return Some(x)
+        }
+
+        if self.blank {
+            return None
+        }
+
+        if !self.wikilinks {
+            // the events of pulldown-cmark, untouched, even the empty texts
+            let (e, r) = self.events.parser.next()?;
+            return Some((e, r.start + self.base_offset..r.end + self.base_offset))
+        }
+
+        match self.next_item()? {
+            Item::Event(e, r) => Some((e, r)),
+            Item::Link(l) => {
+                self.buffer = self.link_events(l);
+                self.buffer.next()
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.events.size_hint();
+        // a peeked event is already out of the parser
+        let ready = self.peeked.is_some() as usize + self.buffer.len();
+        if !self.wikilinks {
+            return (lower.saturating_add(ready), upper.and_then(|u| u.checked_add(ready)))
+        }
+        // a text event can be split into any number of texts and wikilinks,
+        // but each chunk gives at least one event
+        (ready + self.next_chunk.is_some() as usize + lower, None)
+    }
+}
+
+/// The context in which an event was produced.
+///
+/// The start and end tags of a metadata block or a code block
+/// are considered inside the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Context {
+    pub inside_metadata: bool,
+    pub inside_codeblock: bool,
+}
+
+/// An iterator over `(Event, Range, Context)` triples.
+///
+/// Returned by [`ParserOffsetIter::with_context`]
+pub struct WithContext<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for WithContext<'a, 'b> {
+    type Item = (Event<'a>, Range<usize>, Context);
+    fn next(&mut self) -> Option<Self::Item> {
+        let before = (self.parser.inside_metadata, self.parser.inside_codeblock);
+        let (e, r) = self.parser.next()?;
+        let context = Context {
+            inside_metadata: before.0 || self.parser.inside_metadata,
+            inside_codeblock: before.1 || self.parser.inside_codeblock,
+        };
+        Some((e, r, context))
+    }
+}
+
+/// An iterator over `(Event, Range, &str)` triples,
+/// where the string is the part of the source in the range.
+///
+/// Returned by [`ParserOffsetIter::with_source`]
+pub struct WithSource<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for WithSource<'a, 'b> {
+    type Item = (Event<'a>, Range<usize>, &'a str);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, r) = self.parser.next()?;
+        let base = self.parser.base_offset;
+        let source = slice(self.parser.source, r.start - base..r.end - base);
+        Some((e, r, source))
+    }
+}
+
+/// An iterator over the wikilinks of a document.
+///
+/// Returned by [`ParserOffsetIter::wikilinks`]
+pub struct WikiLinks<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for WikiLinks<'a, 'b> {
+    type Item = WikiLink<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Item::Link(l) = self.parser.next_item()? {
+                return Some(l)
+            }
+        }
+    }
+}
+
+/// An iterator over `(block, WikiLink)` pairs.
+///
+/// A wikilink that is not inside a paragraph or a heading, like in a table,
+/// has the index of the last block that started before it.
+///
+/// Returned by [`ParserOffsetIter::wikilinks_with_block`]
+pub struct WikiLinksWithBlock<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+    /// the number of paragraphs and headings that started so far
+    blocks: usize,
+}
+
+impl<'a, 'b> Iterator for WikiLinksWithBlock<'a, 'b> {
+    type Item = (usize, WikiLink<'a>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.parser.next_item()? {
+                Item::Event(Event::Start(Tag::Paragraph | Tag::Heading { .. }), _) => self.blocks += 1,
+                Item::Link(l) => return Some((self.blocks.saturating_sub(1), l)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// An iterator over the events that are not part of a wikilink.
+///
+/// Returned by [`ParserOffsetIter::without_wikilinks`]
+pub struct WithoutWikiLinks<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for WithoutWikiLinks<'a, 'b> {
+    type Item = (Event<'a>, Range<usize>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Item::Event(e, r) = self.parser.next_item()? {
+                return Some((e, r))
+            }
+        }
+    }
+}
+
+/// An event of the document, or a whole wikilink.
+///
+/// Returned by [`ParserOffsetIter::coalesce_wikilinks`]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum CoalescedEvent<'a> {
+    Event(Event<'a>, Range<usize>),
+    WikiLink(WikiLink<'a>),
+}
+
+/// An iterator over the events, where each wikilink is a single item.
+///
+/// Returned by [`ParserOffsetIter::coalesce_wikilinks`]
+pub struct CoalescedWikiLinks<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+}
+
+impl<'a, 'b> Iterator for CoalescedWikiLinks<'a, 'b> {
+    type Item = CoalescedEvent<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(match self.parser.next_item()? {
+            Item::Event(e, r) => CoalescedEvent::Event(e, r),
+            Item::Link(l) => CoalescedEvent::WikiLink(l),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
     use pulldown_cmark::TagEnd;
 
-    use Event::*;
-    use LinkType::*;
+    use Event::*;
+    use LinkType::*;
+
+    /// the wikilinks of `s`, parsed with all the markdown options
+    fn links(s: &str, wiki_options: WikiOptions) -> Vec<WikiLink<'_>> {
+        ParserOffsetIter::new_with(s, Options::all(), wiki_options).wikilinks().collect()
+    }
+
+    #[test]
+    fn parse_text_at_eof() {
+        let s = "some text";
+        let mut parser = WikiParser::new(s, 0..s.len());
+        assert_eq!(parser.parse_text(), Some(0..9));
+        assert_eq!(parser.parse_text(), None);
+
+        let mut parser = WikiParser::new(s, 9..9);
+        assert_eq!(parser.parse_text(), None);
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn parse_no_alias() {
+        let s = "here is a wikilink: [[link]]";
+        let events: Vec<_> =
+            ParserOffsetIter::new_ext(s, Options::all(), true)
+            .collect();
+
+        println!("{events:?}");
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..28),
+                   (Text("here is a wikilink: ".into()), 0..20),
+                   (Start(Tag::Link{link_type: Inline, dest_url: "link".into(), title: "wiki".into(), id: "".into()}), 
+                    20..28),
+                   (Text("link".into()), 22..26),
+                   (End(TagEnd::Link), 20..28),
+                   (End(TagEnd::Paragraph), 0..28),
+        ]);
+    }
+
+    #[test]
+    fn delimiter_ranges() {
+        let s = "[[a|b]]";
+        let links: Vec<_> =
+            ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .collect();
+
+        assert_eq!(links.len(), 1);
+        let link = &links[0];
+        assert_eq!(link.range, 0..7);
+        assert_eq!(link.open_delim_range, 0..2);
+        assert_eq!(link.target_range, 2..3);
+        assert_eq!(link.alias_range, Some(4..5));
+        assert_eq!(link.close_delim_range, 5..7);
+    }
+
+    #[test]
+    fn external_link() {
+        let s = "[[https://x.com]]";
+        let wiki_options = WikiOptions::new().external_links(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(x, _)| x)
+            .collect();
+
+        assert_eq!(events, vec![
+                   Start(Tag::Paragraph),
+                   Start(Tag::Link{link_type: Inline, dest_url: "https://x.com".into(), title: "".into(), id: "".into()}),
+                   Text("https://x.com".into()),
+                   End(TagEnd::Link),
+                   End(TagEnd::Paragraph),
+        ]);
+
+        // the default keeps treating everything as wiki
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .map(|(x, _)| x)
+            .collect();
+        assert_eq!(events[1],
+                   Start(Tag::Link{link_type: Inline, dest_url: "https://x.com".into(), title: "wiki".into(), id: "".into()}));
+    }
+
+    #[test]
+    fn external_mail_link() {
+        let s = "[[mailto:a@b.com|mail]]";
+        let wiki_options = WikiOptions::new().external_links(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(x, _)| x)
+            .collect();
+
+        assert_eq!(events, vec![
+                   Start(Tag::Paragraph),
+                   Start(Tag::Link{link_type: Inline, dest_url: "mailto:a@b.com".into(), title: "".into(), id: "".into()}),
+                   Text("mail".into()),
+                   End(TagEnd::Link),
+                   End(TagEnd::Paragraph),
+        ]);
+    }
+
+    #[test]
+    fn base_offset() {
+        let s = "here is a wikilink: [[link]]";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        let shifted: Vec<_> = ParserOffsetIter::new_ext_at(s, 100, Options::all(), true).collect();
+
+        assert_eq!(events.len(), shifted.len());
+        for ((e, r), (shifted_e, shifted_r)) in events.into_iter().zip(shifted) {
+            assert_eq!(e, shifted_e);
+            assert_eq!(r.start + 100..r.end + 100, shifted_r);
+        }
+
+        let link = ParserOffsetIter::new_ext_at(s, 100, Options::all(), true)
+            .wikilinks()
+            .next()
+            .unwrap();
+        assert_eq!(link.range, 120..128);
+        assert_eq!(link.target_range, 122..126);
+    }
+
+    #[test]
+    fn trim_spaces() {
+        let s = "[[a | b ]]";
+        let wiki_options = WikiOptions::new().trim_spaces(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options.clone())
+            .collect();
+
+        assert_eq!(events[1..4], vec![
+                   (Start(Tag::Link{link_type: Inline, dest_url: "a".into(), title: "wiki".into(), id: "".into()}), 0..10),
+                   (Text("b".into()), 6..7),
+                   (End(TagEnd::Link), 0..10),
+        ]);
+
+        let link = links(s, wiki_options).remove(0);
+        assert_eq!(link.target_range, 2..3);
+        assert_eq!(link.alias_range, Some(6..7));
+    }
+
+    #[test]
+    fn single_wikilink() {
+        let link = parse_single_wikilink("  [[a|b]] ").unwrap();
+        assert_eq!(link.target, "a".into());
+        assert_eq!(link.display, "b".into());
+        assert_eq!(link.range, 2..9);
+
+        assert!(parse_single_wikilink("[[unterminated").is_none());
+        assert!(parse_single_wikilink("[[a]] and text").is_none());
+        assert!(parse_single_wikilink("[[]]").is_none());
+        assert!(parse_single_wikilink("").is_none());
+    }
+
+    #[test]
+    fn valid_target() {
+        assert!(is_valid_wikilink_target("my note"));
+        assert!(is_valid_wikilink_target("a [b] c"));
+        assert!(!is_valid_wikilink_target("a|b"));
+        assert!(!is_valid_wikilink_target("a]]b"));
+        assert!(!is_valid_wikilink_target("a\nb"));
+        assert!(!is_valid_wikilink_target("  "));
+        assert!(!is_valid_wikilink_target(""));
+    }
+
+    #[test]
+    fn nested_brackets() {
+        for (s, target) in [("[[[x]]]", "[x]"), ("[[a[b]]]", "a[b]")] {
+            let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+                .collect();
+
+            assert_eq!(events, vec![
+                       (Start(Tag::Paragraph), 0..s.len()),
+                       (Start(Tag::Link{link_type: Inline, dest_url: target.into(), title: "wiki".into(), id: "".into()}), 0..s.len()),
+                       (Text(target.into()), 2..s.len()-2),
+                       (End(TagEnd::Link), 0..s.len()),
+                       (End(TagEnd::Paragraph), 0..s.len()),
+            ]);
+        }
+    }
+
+    #[test]
+    fn closing_bracket_after_link() {
+        for (s, range) in [("[see [[a]]]", 5..10), ("[[a]]]", 0..5)] {
+            let link = links(s, WikiOptions::new()).remove(0);
+            assert_eq!(link.target, "a".into());
+            assert_eq!(link.range, range);
+
+            let texts: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+                .filter(|(e, _)| matches!(e, Text(_)))
+                .collect();
+            assert_eq!(texts.last(), Some(&(Text("]".into()), range.end..s.len())));
+        }
+    }
+
+    #[test]
+    fn partial_input() {
+        let wiki_options = WikiOptions::new().partial_input(true);
+
+        let s = "some text [[par";
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options.clone())
+            .collect();
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..15),
+                   (Text("some text ".into()), 0..10),
+                   (Text("[[par".into()), 10..15),
+                   (End(TagEnd::Paragraph), 0..15),
+        ]);
+
+        let s = "[[par and [[b]]";
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(x, _)| x)
+            .collect();
+        assert_eq!(events, vec![
+                   Start(Tag::Paragraph),
+                   Text("[[par and ".into()),
+                   Start(Tag::Link{link_type: Inline, dest_url: "b".into(), title: "wiki".into(), id: "".into()}),
+                   Text("b".into()),
+                   End(TagEnd::Link),
+                   End(TagEnd::Paragraph),
+        ]);
+    }
+
+    #[test]
+    fn link_types() {
+        let s = "[[a]] [[a|b]] ![[c]] [[d#e]] [[d#^f]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let types: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.link_type).collect();
+
+        use WikiLinkType::*;
+        assert_eq!(types, vec![Shortcut, Aliased, Transclusion, Anchor, BlockRef]);
+    }
+
+    #[test]
+    fn embed() {
+        let s = "see ![[c.png]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .collect();
+
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..14),
+                   (Text("see ".into()), 0..4),
+                   (Start(Tag::Image{link_type: Inline, dest_url: "c.png".into(), title: "wiki".into(), id: "".into()}), 4..14),
+                   (Text("c.png".into()), 7..12),
+                   (End(TagEnd::Image), 4..14),
+                   (End(TagEnd::Paragraph), 0..14),
+        ]);
+
+        // without the option, the `!` is a normal text
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .collect();
+        assert_eq!(events[1], (Text("see !".into()), 0..5));
+    }
+
+    #[test]
+    fn raw_targets() {
+        let s = "[[  weird\ttarget |b]]";
+        let wiki_options = WikiOptions::new().trim_spaces(true).raw_targets(true);
+        let link = links(s, wiki_options).remove(0);
+
+        assert_eq!(link.target, "  weird\ttarget ".into());
+        assert_eq!(link.target_range, 2..17);
+        assert_eq!(link.display, "b".into());
+    }
+
+    #[test]
+    fn transform_targets() {
+        let s = "[[My Note]]";
+        let slugify = |s: &str| s.to_lowercase().replace(' ', "-");
+        let prefix = |s: &str| format!("/wiki/{s}");
+        let wiki_options = WikiOptions::new().transform(slugify.then(prefix));
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(x, _)| x)
+            .collect();
+
+        assert_eq!(events[1..4], vec![
+                   Start(Tag::Link{link_type: Inline, dest_url: "/wiki/my-note".into(), title: "wiki".into(), id: "".into()}),
+                   Text("My Note".into()),
+                   End(TagEnd::Link),
+        ]);
+    }
+
+    #[test]
+    fn sized_embed() {
+        let s = "![[image.png|100]] ![[image.png|100x200]] ![[image.png|alt]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let links = links(s, wiki_options);
+
+        assert_eq!(links[0].embed_params, Some(EmbedParams { alt: None, width: Some(100), height: None }));
+        assert_eq!(links[0].display, "image.png".into());
+        assert_eq!(links[1].embed_params, Some(EmbedParams { alt: None, width: Some(100), height: Some(200) }));
+        assert_eq!(links[1].display, "image.png".into());
+        assert_eq!(links[2].embed_params, Some(EmbedParams { alt: Some("alt".into()), width: None, height: None }));
+        assert_eq!(links[2].display, "alt".into());
+    }
+
+    #[test]
+    fn embed_with_several_fields() {
+        let s = "![[a.png|caption|300]] ![[a.png|200x100]] ![[a.png| |]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let links = links(s, wiki_options);
+
+        assert_eq!(links[0].embed_params, Some(EmbedParams { alt: Some("caption".into()), width: Some(300), height: None }));
+        assert_eq!(links[0].display, "caption".into());
+        assert_eq!(links[0].display_range, 9..16);
+        assert_eq!(links[1].embed_params, Some(EmbedParams { alt: None, width: Some(200), height: Some(100) }));
+        assert_eq!(links[1].display, "a.png".into());
+        assert_eq!(links[2].embed_params, None);
+        assert_eq!(links[2].display, "a.png".into());
+    }
+
+    #[test]
+    fn image_or_transclusion() {
+        let s = "![[a.png]] ![[Note]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(events[1], Start(Tag::Image{link_type: Inline, dest_url: "a.png".into(), title: WIKI_MARKER.into(), id: "".into()}));
+        assert_eq!(events[5], Start(Tag::Link{link_type: Inline, dest_url: "Note".into(), title: TRANSCLUSION_MARKER.into(), id: "".into()}));
+        assert_eq!(events[7], End(TagEnd::Link));
+
+        // the set of extensions is configurable, and ignores the case
+        let wiki_options = WikiOptions::new().embeds(true).image_extensions(&["jpg"]);
+        let types: Vec<_> = links("![[a.png]] ![[b.JPG]]", wiki_options)
+            .into_iter()
+            .map(|l| l.link_type)
+            .collect();
+        assert_eq!(types, vec![WikiLinkType::Transclusion, WikiLinkType::Embed]);
+    }
+
+    #[test]
+    fn double_pipe() {
+        let s = "[[a||b]]";
+        let link = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .next()
+            .unwrap();
+        assert_eq!(link.target, "a".into());
+        assert_eq!(link.display, "|b".into());
+
+        let wiki_options = WikiOptions::new().strict_pipes(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .collect();
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..8),
+                   (Text("[[a||".into()), 0..5),
+                   (Text("b]]".into()), 5..8),
+                   (End(TagEnd::Paragraph), 0..8),
+        ]);
+    }
+
+    #[test]
+    fn custom_separator() {
+        let s = "[[a::b]] [[c|d]]";
+        let wiki_options = WikiOptions::new().separator("::");
+        let links: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.display)).collect();
+
+        assert_eq!(links, vec![
+                   ("a".into(), "b".into()),
+                   ("c|d".into(), "c|d".into()),
+        ]);
+    }
+
+    #[test]
+    fn size_hint() {
+        let s = "some text and a [[link]]";
+        let mut parser = ParserOffsetIter::new_ext(s, Options::all(), true);
+        assert!(parser.size_hint().0 > 0);
+
+        let mut count = 0;
+        while parser.next().is_some() {
+            count += 1;
+            assert!(parser.size_hint().0 <= 6 - count);
+        }
+        assert_eq!(count, 6);
+        assert_eq!(parser.size_hint().0, 0);
+
+        assert_eq!(ParserOffsetIter::new_ext("", Options::all(), true).size_hint().0, 0);
+
+        // without the wikilinks, the peeked event is counted
+        let mut parser = ParserOffsetIter::new_ext(s, Options::all(), false);
+        parser.peek();
+        let (lower, upper) = parser.size_hint();
+        let remaining = parser.count();
+        assert!(lower >= 1 && lower <= remaining);
+        if let Some(upper) = upper {
+            assert!(remaining <= upper);
+        }
+    }
+
+    #[test]
+    fn block_link() {
+        let s = "[[note]]\n\nsee [[other]]";
+        let blocks: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .map(|l| (l.target, l.block))
+            .collect();
+
+        assert_eq!(blocks, vec![("note".into(), true), ("other".into(), false)]);
+    }
+
+    #[test]
+    fn greedy_close() {
+        let s = "[[a]]b]] and [[c]]";
+        let targets: Vec<_> = links(s, WikiOptions::new()).into_iter().map(|l| l.target).collect();
+        assert_eq!(targets, vec!["a".into(), CowStr::from("c")]);
+
+        let wiki_options = WikiOptions::new().greedy_close(true);
+        let targets: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.target).collect();
+        assert_eq!(targets, vec!["a]]b".into(), CowStr::from("c")]);
+    }
+
+    #[test]
+    fn emphasized_link() {
+        let s = "*[[link]]* **[[b]]**";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+
+        let wiki = |t: &'static str| Start(Tag::Link{link_type: Inline, dest_url: t.into(), title: "wiki".into(), id: "".into()});
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..20),
+                   (Start(Tag::Emphasis), 0..10),
+                   (wiki("link"), 1..9),
+                   (Text("link".into()), 3..7),
+                   (End(TagEnd::Link), 1..9),
+                   (End(TagEnd::Emphasis), 0..10),
+                   (Text(" ".into()), 10..11),
+                   (Start(Tag::Strong), 11..20),
+                   (wiki("b"), 13..18),
+                   (Text("b".into()), 15..16),
+                   (End(TagEnd::Link), 13..18),
+                   (End(TagEnd::Strong), 11..20),
+                   (End(TagEnd::Paragraph), 0..20),
+        ]);
+    }
+
+    #[test]
+    fn link_before_emphasis() {
+        let s = "[[link]]*x*";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+
+        assert_eq!(events[1..], [
+                   (Start(Tag::Link{link_type: Inline, dest_url: "link".into(), title: "wiki".into(), id: "".into()}), 0..8),
+                   (Text("link".into()), 2..6),
+                   (End(TagEnd::Link), 0..8),
+                   (Start(Tag::Emphasis), 8..11),
+                   (Text("x".into()), 9..10),
+                   (End(TagEnd::Emphasis), 8..11),
+                   (End(TagEnd::Paragraph), 0..11),
+        ]);
+    }
+
+    #[test]
+    fn newline_terminates_link() {
+        let s = "[[unclosed\nnext line [[b]]";
+        let options = WikiOptions::new().newline_terminates_link(true);
+        let events: Vec<_> = WikiParser::new_at_lines(s, 0..s.len(), options).collect();
+
+        assert_eq!(events, vec![
+                   (Text("[[unclosed\n".into()), 0..11),
+                   (Text("next line ".into()), 11..21),
+                   (Start(Tag::Link{link_type: Inline, dest_url: "b".into(), title: "wiki".into(), id: "".into()}), 21..26),
+                   (Text("b".into()), 23..24),
+                   (End(TagEnd::Link), 21..26),
+        ]);
+
+        // in the alias too
+        let s = "[[a|b\nc]]";
+        let options = WikiOptions::new().newline_terminates_link(true);
+        let mut parser = WikiParser::new_at_lines(s, 0..s.len(), options);
+        assert_eq!(parser.next(), Some((Text("[[a|b\n".into()), 0..6)));
+    }
+
+    #[test]
+    fn allowed_chars() {
+        let s = "[[a<b>]] [[ok]]";
+        let no_html = |c: char| c != '<' && c != '>';
+
+        // `<b>` is inline html, that splits the text otherwise
+        let wiki_options = WikiOptions::new().bridge_inline_html(true).allowed_chars(no_html);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options).collect();
+        assert_eq!(events[1], (Text("[[a<b>]]".into()), 0..8));
+        assert_eq!(events[3].1, 9..15);
+
+        let wiki_options = WikiOptions::new()
+            .bridge_inline_html(true)
+            .allowed_chars(no_html)
+            .sanitize_targets(true);
+        let link = links(s, wiki_options).remove(0);
+        assert_eq!(link.target, "ab".into());
+        assert_eq!(link.display, "a<b>".into());
+    }
+
+    #[test]
+    fn reset() {
+        let mut parser = ParserOffsetIter::new_ext("```\n[[a]]", Options::all(), true);
+        // stop in the middle of the code block
+        parser.next();
+
+        let second = "[[b]]";
+        parser.reset(second);
+        let events: Vec<_> = parser.collect();
+        assert_eq!(events, ParserOffsetIter::new_ext(second, Options::all(), true).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn anchor_with_alias() {
+        let s = "[[Page#Section|Label]] [[#Section|Label]]";
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .collect();
+
+        assert_eq!(links[0].target, "Page#Section".into());
+        assert_eq!(links[0].display, "Label".into());
+        assert_eq!(links[0].target_range, 2..14);
+        assert_eq!(links[0].alias_range, Some(15..20));
+        assert_eq!(links[0].link_type, WikiLinkType::Anchor);
+
+        // on the same page
+        assert_eq!(links[1].target, "#Section".into());
+        assert_eq!(links[1].display, "Label".into());
+        assert_eq!(links[1].link_type, WikiLinkType::Anchor);
+
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).map(|(e, _)| e).collect();
+        assert_eq!(events[1], Start(Tag::Link{link_type: Inline, dest_url: "Page#Section".into(), title: "wiki".into(), id: "".into()}));
+        assert_eq!(events[2], Text("Label".into()));
+    }
+
+    #[test]
+    fn display_mode() {
+        let display = |s: &str, mode| {
+            let wiki_options = WikiOptions::new().display_mode(mode);
+            let link = links(s, wiki_options).remove(0);
+            assert_eq!(&*link.display, &s[link.display_range.clone()]);
+            link.display.to_string()
+        };
+
+        assert_eq!(display("[[a/b/c]]", DisplayMode::AliasOrTarget), "a/b/c");
+        assert_eq!(display("[[a/b/c|]]", DisplayMode::AliasOrTarget), "");
+        assert_eq!(display("[[a/b/c| ]]", DisplayMode::AliasOrTarget), "a/b/c");
+        assert_eq!(display("[[a/b/c|]]", DisplayMode::FullTarget), "a/b/c");
+        assert_eq!(display("[[a/b/c]]", DisplayMode::LastSegment), "c");
+        assert_eq!(display("[[a/b/c|]]", DisplayMode::LastSegment), "c");
+        assert_eq!(display("[[a/b/c|d]]", DisplayMode::LastSegment), "d");
+        assert_eq!(display("[[a/b/c| ]]", DisplayMode::LastSegment), "c");
+    }
+
+    #[test]
+    fn blank_alias() {
+        let wiki_options = WikiOptions::new().display_mode(DisplayMode::FullTarget).trim_spaces(true);
+        let pairs: Vec<_> = links("[[Page | ]] [[Page#Part|  ]]", wiki_options)
+            .into_iter()
+            .map(|l| (l.target, l.display))
+            .collect();
+
+        assert_eq!(pairs, vec![
+                   ("Page".into(), "Page".into()),
+                   ("Page#Part".into(), "Page#Part".into()),
+        ]);
+
+        // with the default options too
+        let displays: Vec<_> = links("[[Page | ]] [[Page#Part|  ]] [[Page|]]", WikiOptions::new())
+            .into_iter()
+            .map(|l| l.display)
+            .collect();
+        assert_eq!(displays, vec!["Page".into(), CowStr::from("Page#Part"), "".into()]);
+    }
+
+    #[test]
+    fn definition_list() {
+        // the version of pulldown-cmark in use has no definition lists,
+        // so this is a paragraph, but the links must be found either way
+        let s = "Term [[a]]\n: Definition [[b|c]]";
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .map(|l| (l.target, l.range))
+            .collect();
+
+        assert_eq!(links, vec![("a".into(), 5..10), ("b".into(), 24..31)]);
+    }
+
+    #[test]
+    fn without_wikilinks() {
+        let s = "see [[a|the link]] and *more*";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .without_wikilinks()
+            .collect();
+
+        assert_eq!(events, vec![
+                   (Start(Tag::Paragraph), 0..29),
+                   (Text("see ".into()), 0..4),
+                   (Text(" and ".into()), 18..23),
+                   (Start(Tag::Emphasis), 23..29),
+                   (Text("more".into()), 24..28),
+                   (End(TagEnd::Emphasis), 23..29),
+                   (End(TagEnd::Paragraph), 0..29),
+        ]);
+    }
+
+    #[test]
+    fn same_page() {
+        let s = "[[#Section]] [[^blockid]] [[#^blockid]]";
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .map(|l| (l.target, l.display, l.link_type))
+            .collect();
+
+        use WikiLinkType::*;
+        assert_eq!(links, vec![
+                   ("#Section".into(), "Section".into(), Anchor),
+                   ("#^blockid".into(), "^blockid".into(), BlockRef),
+                   ("#^blockid".into(), "^blockid".into(), BlockRef),
+        ]);
+    }
+
+    #[test]
+    fn dedupe() {
+        let s = "[[b]] [[a]] [[b]] [[a|x]] [[a]]";
+        let links = || ParserOffsetIter::new_ext(s, Options::all(), true).wikilinks();
+
+        // the same links at different places are equal
+        let set: std::collections::HashSet<_> = links().collect();
+        assert_eq!(set.len(), 3);
+        let set: std::collections::BTreeSet<_> = links().collect();
+        assert_eq!(set.len(), 3);
+
+        let keys: Vec<_> = set.iter().map(|l| l.key()).collect();
+        assert_eq!(keys, vec![("a", "a"), ("a", "x"), ("b", "b")]);
+    }
+
+    #[test]
+    fn title_case_display() {
+        let s = "[[my note]] [[NASA rockets]] [[my note|my note]]";
+        let wiki_options = WikiOptions::new().title_case_display(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(events[1], Start(Tag::Link{link_type: Inline, dest_url: "my note".into(), title: "wiki".into(), id: "".into()}));
+        assert_eq!(events[2], Text("My Note".into()));
+        assert_eq!(events[6], Text("NASA Rockets".into()));
+        // an alias is displayed as it is
+        assert_eq!(events[10], Text("my note".into()));
+    }
+
+    #[test]
+    fn html_inside_link() {
+        let s = "[[a<br>b]] <b>c</b>";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        assert_eq!(events[1..4], [
+                   (Text("[[a".into()), 0..3),
+                   (Html("<br>".into()), 3..7),
+                   (Text("b]] ".into()), 7..11),
+        ]);
+
+        let wiki_options = WikiOptions::new().bridge_inline_html(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options).collect();
+        assert_eq!(events[1..6], [
+                   (Start(Tag::Link{link_type: Inline, dest_url: "a<br>b".into(), title: "wiki".into(), id: "".into()}), 0..10),
+                   (Text("a<br>b".into()), 2..8),
+                   (End(TagEnd::Link), 0..10),
+                   (Text(" ".into()), 10..11),
+                   // outside of a link, the html is kept
+                   (Html("<b>".into()), 11..14),
+        ]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "range 0..1 is not on a char boundary")]
+    fn bad_range() {
+        slice("é", 0..1);
+    }
+
+    #[test]
+    fn streams_long_paragraphs() {
+        let s = "[[a]] ".repeat(10_000);
+        let mut parser = ParserOffsetIter::new_ext(&s, Options::all(), true);
+        parser.nth(1);
+
+        // only the next link is parsed ahead, not the whole paragraph
+        assert!(parser.next_chunk.is_some());
+        assert!(parser.size_hint().0 < 10);
+        assert_eq!(parser.wikilinks().count(), 10_000 - 1);
+    }
+
+    #[test]
+    fn target_prefix() {
+        let s = "[[Note]] [[Note#Heading]] [[#Heading]] [[https://a.org]]";
+        let wiki_options = WikiOptions::new()
+            .external_links(true)
+            .target_prefix("/wiki/")
+            .target_suffix(".html");
+        let targets: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.target).collect();
+
+        assert_eq!(targets, vec![
+                   CowStr::from("/wiki/Note.html"),
+                   "/wiki/Note.html#Heading".into(),
+                   "#Heading".into(),
+                   "https://a.org".into(),
+        ]);
+    }
+
+    #[test]
+    fn footnotes() {
+        let s = "see[^1] and [[x]], [^2][[y]]\n\n[^1]: a note";
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::ENABLE_FOOTNOTES, true)
+            .wikilinks()
+            .map(|l| (l.target, l.range))
+            .collect();
+
+        assert_eq!(links, vec![("x".into(), 12..17), ("y".into(), 23..28)]);
+    }
+
+    #[test]
+    fn prose_passthrough() {
+        let s = "Some *prose* with [single] brackets,\nand ]] or [ [ but no link.\n\n> quoted";
+        let parsed: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        let joined: Vec<_> = joined_events(s, Options::all()).collect();
+        assert_eq!(parsed, joined);
+    }
+
+    #[test]
+    fn with_source() {
+        let s = "# Title\n\nsee ![[a.png]] and [[b|c]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options);
+        for (_, r, source) in parser.with_source() {
+            assert_eq!(source, &s[r]);
+        }
+
+        let slices: Vec<_> = ParserOffsetIter::new_ext_at("[[b|c]]", 10, Options::all(), true)
+            .with_source()
+            .map(|(_, _, source)| source)
+            .collect();
+        assert_eq!(slices, vec!["[[b|c]]", "[[b|c]]", "c", "[[b|c]]", "[[b|c]]"]);
+    }
+
+    #[test]
+    fn strip_chars() {
+        let s = "[[my\u{200B}note]] [[other]]";
+        let wiki_options = WikiOptions::new().strip_chars(INVISIBLE_CHARS);
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options);
+        let events: Vec<_> = parser.by_ref().collect();
+
+        assert_eq!(events[1], (Start(Tag::Link{link_type: Inline, dest_url: "mynote".into(), title: "wiki".into(), id: "".into()}), 0..13));
+        // the display is the source
+        assert_eq!(events[2], (Text("my\u{200B}note".into()), 2..11));
+        assert_eq!(parser.stats(), ParseStats { wikilinks: 2, stripped_chars: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn target_parts() {
+        fn parts(s: &str, fragments: bool, blocks: bool) -> (&str, Option<&str>, Option<&str>) {
+            let options = WikiOptions::new().split_fragments(fragments).split_block_refs(blocks);
+            let mut parser = WikiParser::new_with(s, 0..s.len(), Arc::new(options));
+            let l = match parser.next_chunk() {
+                Some(Chunk::Link(l)) => l.parts,
+                _ => panic!("no link in {s}"),
+            };
+            (&s[l.page], l.fragment.map(|r| &s[r]), l.block.map(|r| &s[r]))
+        }
+
+        for (fragments, blocks) in [(false, false), (true, false), (false, true), (true, true)] {
+            assert_eq!(parts("[[Page|a]]", fragments, blocks), ("Page", None, None));
+        }
+
+        assert_eq!(parts("[[P#H]]", false, false), ("P#H", None, None));
+        assert_eq!(parts("[[P#H]]", true, false), ("P", Some("H"), None));
+        assert_eq!(parts("[[P#H]]", false, true), ("P#H", None, None));
+        assert_eq!(parts("[[P#H]]", true, true), ("P", Some("H"), None));
+
+        assert_eq!(parts("[[P#^b]]", false, false), ("P#^b", None, None));
+        assert_eq!(parts("[[P#^b]]", true, false), ("P", Some("^b"), None));
+        assert_eq!(parts("[[P#^b]]", false, true), ("P", None, Some("b")));
+        assert_eq!(parts("[[P#^b]]", true, true), ("P", None, Some("b")));
+
+        assert_eq!(parts("[[^b]]", false, false), ("^b", None, None));
+        assert_eq!(parts("[[^b]]", true, true), ("", None, Some("b")));
+        assert_eq!(parts("[[#H]]", true, true), ("", Some("H"), None));
+    }
+
+    #[test]
+    fn on_wikilink() {
+        let s = "[[a]] [[b|c]]\n\n[[a]]";
+        let mut index = Vec::new();
+        let events = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .on_wikilink(|l| index.push((l.target.to_string(), l.range.clone())))
+            .count();
+
+        assert_eq!(events, 14);
+        assert_eq!(index, vec![
+                   ("a".to_string(), 0..5),
+                   ("b".to_string(), 6..13),
+                   ("a".to_string(), 15..20),
+        ]);
+    }
+
+    #[test]
+    fn empty_options() {
+        let s = "# [[a]]\n\n- [[b|c]]\n\n| [[d]] |\n| --- |\n\n~[[e]]~";
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::empty(), true)
+            .wikilinks()
+            .map(|l| (l.target, l.range))
+            .collect();
+
+        assert_eq!(links, vec![
+                   ("a".into(), 2..7),
+                   ("b".into(), 11..18),
+                   ("d".into(), 22..27),
+                   ("e".into(), 40..45),
+        ]);
+    }
 
     #[test]
-    fn parse_no_alias() {
-        let s = "here is a wikilink: [[link]]";
-        let events: Vec<_> =
-            ParserOffsetIter::new_ext(s, Options::all(), true)
+    fn current_dir() {
+        let s = "[[../x]] [[./y|y]] [[z]]";
+        let wiki_options = WikiOptions::new().current_dir("notes/sub");
+        let targets: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.target).collect();
+
+        assert_eq!(targets, vec![CowStr::from("notes/x"), "notes/sub/y".into(), "z".into()]);
+    }
+
+    #[test]
+    fn escaped_brackets() {
+        let s = r"[[a\]]b]] [[c|d\]]]]";
+        let wiki_options = WikiOptions::new().escaped_brackets(true);
+        let links: Vec<_> = links(s, wiki_options)
+            .into_iter()
+            .map(|l| (l.target, l.display, l.range))
             .collect();
 
-        println!("{events:?}");
+        assert_eq!(links, vec![
+                   ("a]]b".into(), "a]]b".into(), 0..9),
+                   ("c".into(), "d]]".into(), 10..20),
+        ]);
+    }
+
+    #[test]
+    fn generate_ids() {
+        let s = "[[My Note]] [[Über  note#2|x]]";
+        let wiki_options = WikiOptions::new().generate_ids(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(events[1], Start(Tag::Link{link_type: Inline, dest_url: "My Note".into(), title: "wiki".into(), id: "wikilink-my-note".into()}));
+        assert_eq!(events[5], Start(Tag::Link{link_type: Inline, dest_url: "Über  note#2".into(), title: "wiki".into(), id: "wikilink-über-note-2".into()}));
+    }
+
+    #[test]
+    fn link_across_line_break() {
+        for s in ["[[a\\\nb]]", "[[a  \nb]]", "[[a\nb]]"] {
+            let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+            assert!(!events.iter().any(|(e, _)| matches!(e, Start(Tag::Link { .. }))));
+            assert!(matches!(&events[1], (Text(t), r) if t.starts_with("[[a") && r.start == 0));
+        }
+    }
+
+    #[test]
+    fn spaces_replaced() {
+        let s = "[[My Note]] [[My Note#A B|alias]]";
+        let wiki_options = WikiOptions::new().space_replacement('_').target_prefix("/wiki/");
+        let links = links(s, wiki_options);
+
+        assert_eq!(links[0].target, "/wiki/My_Note".into());
+        assert_eq!(links[0].display, "My Note".into());
+        assert_eq!(links[1].target, "/wiki/My_Note#A_B".into());
+    }
+
+    #[test]
+    fn peek_then_next() {
+        let s = "a [[b]]";
+        let mut parser = ParserOffsetIter::new_ext(s, Options::all(), true);
+        let all: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+
+        let mut events = Vec::new();
+        while let Some(peeked) = parser.peek().cloned() {
+            assert_eq!(parser.peek(), Some(&peeked));
+            assert!(parser.size_hint().0 >= 1);
+            assert_eq!(parser.next(), Some(peeked.clone()));
+            events.push(peeked);
+        }
+        assert_eq!(events, all);
+        assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn deeply_nested_link() {
+        // the state of the parser is a few flags, that don't grow with the nesting
+        let s = format!("{}[[link]]", "> ".repeat(2000));
+        let links: Vec<_> = ParserOffsetIter::new_ext(&s, Options::all(), true)
+            .wikilinks()
+            .collect();
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].target, "link".into());
+        assert_eq!(links[0].range, 4000..4008);
+    }
+
+    #[test]
+    fn roam_block_refs() {
+        let s = "see ((abc123)) and [[Page]], not ((a b)) or (c)";
+        let wiki_options = WikiOptions::new().roam_block_refs(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options).collect();
+
+        assert_eq!(events[2], (Start(Tag::Link{link_type: Inline, dest_url: "abc123".into(), title: ROAM_BLOCK_REF_MARKER.into(), id: "".into()}), 4..14));
+        assert_eq!(events[3], (Text("abc123".into()), 6..12));
+        assert_eq!(events[6], (Start(Tag::Link{link_type: Inline, dest_url: "Page".into(), title: "wiki".into(), id: "".into()}), 19..27));
+        let rest: String = events[9..]
+            .iter()
+            .map(|(e, _)| match e {
+                Text(t) => t.as_ref(),
+                End(TagEnd::Paragraph) => "",
+                _ => panic!("{e:?} should be a text"),
+            })
+            .collect();
+        assert_eq!(rest, ", not ((a b)) or (c)");
+    }
+
+    #[test]
+    fn roam_block_ref_parts() {
+        let wiki_options = WikiOptions::new().roam_block_refs(true);
+        let links = links("((abc123))", wiki_options);
+
+        assert_eq!(links[0].link_type, WikiLinkType::RoamBlockRef);
+        assert_eq!(links[0].parts.block, Some(2..8));
+
+        let links: Vec<_> = ParserOffsetIter::new_ext("((abc123))", Options::all(), true).wikilinks().collect();
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn github_fragments() {
+        let s = "[[Page#My Heading]] [[#What's new?]] [[Page#^Block]] [[Page#A - b_c]]";
+        let wiki_options = WikiOptions::new()
+            .fragment_style(FragmentStyle::GithubSlug)
+            .transform(|s: &str| s.to_lowercase())
+            .target_suffix(".html");
+        let targets: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.display)).collect();
+
+        assert_eq!(targets, vec![
+            ("page.html#my-heading".into(), "Page#My Heading".into()),
+            ("#whats-new".into(), "What's new?".into()),
+            ("page.html#^block".into(), "Page#^Block".into()),
+            ("page.html#a---b_c".into(), "Page#A - b_c".into()),
+        ]);
+    }
+
+    #[test]
+    fn same_as_upstream_without_wikilinks() {
+        let s = "# Title\n\n| a | **b** |\n| - | - |\n| c | [[d]] |\n\n> quote with [[link|alias]]\n\n".repeat(200);
+        let upstream: Vec<_> = _Parser::new_ext(&s, Options::all()).into_offset_iter().collect();
+        let events: Vec<_> = ParserOffsetIter::new_ext(&s, Options::all(), false).collect();
+        assert_eq!(events, upstream);
+
+        let mut shifted = ParserOffsetIter::new_ext_at(&s, 10, Options::all(), false);
+        assert_eq!(shifted.peek().map(|(_, r)| r.clone()), Some(upstream[0].1.start + 10..upstream[0].1.end + 10));
+        assert_eq!(shifted.nth(1).unwrap().1, upstream[1].1.start + 10..upstream[1].1.end + 10);
+    }
+
+    #[test]
+    fn extra_delimiters() {
+        let s = "[[a]] and {{ b | c }} or {{d}}";
+        let wiki_options = WikiOptions::new().delimiters(&[("{{", "}}", "template")]);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(events[1], Start(Tag::Link{link_type: Inline, dest_url: "a".into(), title: "wiki".into(), id: "".into()}));
+        assert_eq!(events[5], Start(Tag::Link{link_type: Inline, dest_url: "b".into(), title: "template".into(), id: "".into()}));
+        assert_eq!(events[6], Text("c".into()));
+        assert_eq!(events[9], Start(Tag::Link{link_type: Inline, dest_url: "d".into(), title: "template".into(), id: "".into()}));
+
+        let wiki_options = WikiOptions::new().delimiters(&[("{{", "}}", "template")]);
+        let links = links(s, wiki_options);
+        assert_eq!(links[0].kind, None);
+        assert_eq!(links[1].kind.as_deref(), Some("template"));
+        assert_eq!(links[1].target_range, 13..14);
+    }
+
+    #[test]
+    #[should_panic(expected = "a delimiter can't be empty")]
+    fn empty_delimiter() {
+        WikiOptions::new().delimiters(&[("", "}}", "template")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the delimiter \"[\" can't start with a bracket")]
+    fn bracket_delimiter() {
+        WikiOptions::new().delimiters(&[("[", "]", "single")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the delimiter \"::}\" can't start with the separator")]
+    fn separator_delimiter() {
+        WikiOptions::new().delimiters(&[("{{", "::}", "template")]).separator("::");
+    }
+
+    #[test]
+    fn unclosed_extra_delimiters() {
+        let s = "{{a [[b]] {{}}";
+        let wiki_options = WikiOptions::new().delimiters(&[("{{", "}}", "template")]);
+        let links = links(s, wiki_options);
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].target, "b".into());
+    }
+
+    #[test]
+    fn path_traversal() {
+        let s = "[[../../etc/passwd]] [[a/..\\b]] [[a..b]] [[Page#..]] [[ok]]";
+        let mut parser = ParserOffsetIter::new_ext(s, Options::all(), true);
+        let targets: Vec<_> = parser.by_ref().filter_map(|(e, _)| match e {
+            Start(Tag::Link { dest_url, .. }) => Some(dest_url),
+            _ => None,
+        }).collect();
+        assert_eq!(targets.len(), 5);
+        assert_eq!(parser.stats().path_traversals, 2);
+
+        let wiki_options = WikiOptions::new().reject_path_traversal(true);
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options);
+        let links: Vec<_> = parser.by_ref().filter_map(|(e, _)| match e {
+            Start(Tag::Link { dest_url, .. }) => Some(dest_url),
+            _ => None,
+        }).collect();
+        assert_eq!(links, vec!["a..b".into(), "Page#..".into(), "ok".into()]);
+        assert_eq!(parser.stats().path_traversals, 2);
+    }
+
+    #[test]
+    fn folder_index() {
+        let s = "[[folder/]] [[a/b/#Intro]] [[a/b/|alias]] [[/]] [[note]]";
+        let wiki_options = WikiOptions::new().folder_index("index");
+        let links: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.display)).collect();
+
+        assert_eq!(links, vec![
+            ("folder/index".into(), "folder".into()),
+            ("a/b/index#Intro".into(), "a/b/#Intro".into()),
+            ("a/b/index".into(), "alias".into()),
+            ("/index".into(), "/".into()),
+            ("note".into(), "note".into()),
+        ]);
+    }
+
+    #[test]
+    fn coalesced_wikilinks() {
+        let s = "a [[b|c]] d";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .coalesce_wikilinks()
+            .collect();
+
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0], CoalescedEvent::Event(Start(Tag::Paragraph), 0..11));
+        assert_eq!(events[1], CoalescedEvent::Event(Text("a ".into()), 0..2));
+        assert!(matches!(&events[2], CoalescedEvent::WikiLink(l) if l.display == "c".into() && l.range == (2..9)));
+        assert_eq!(events[3], CoalescedEvent::Event(Text(" d".into()), 9..11));
+        assert_eq!(events[4], CoalescedEvent::Event(End(TagEnd::Paragraph), 0..11));
+
+        // markdown splits the text, so there is no wikilink, and the events are kept
+        let s = "a [[b|*c*]] d";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        let coalesced: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .coalesce_wikilinks()
+            .collect();
+        assert_eq!(coalesced, events.into_iter().map(|(e, r)| CoalescedEvent::Event(e, r)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn quoted_targets() {
+        let s = r#"[["a b"]] [['c']] [["a]] [["d"|alias]] [["]]"#;
+        let wiki_options = WikiOptions::new().unquote_targets(true);
+        let links: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.display)).collect();
+
+        assert_eq!(links, vec![
+            ("a b".into(), "a b".into()),
+            ("c".into(), "c".into()),
+            ("\"a".into(), "\"a".into()),
+            ("d".into(), "alias".into()),
+            ("\"".into(), "\"".into()),
+        ]);
+    }
+
+    #[test]
+    fn single_bracket_links() {
+        let s = "see [Page] and [[Other]], not [] or [^1] or [a [b] c";
+        let wiki_options = WikiOptions::new().single_bracket_links(true);
+        let links: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.range)).collect();
+
+        assert_eq!(links, vec![
+            ("Page".into(), 4..10),
+            ("Other".into(), 15..24),
+            ("b".into(), 47..50),
+        ]);
+
+        // without the option, `[Page]` is text
+        let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).wikilinks().collect();
+        assert_eq!(links.len(), 1);
+    }
+
+    #[test]
+    fn single_brackets_and_markdown_links() {
+        let s = "[text](url) [ref][id] [ref][] [x]\n\n[id]: /url";
+        let wiki_options = WikiOptions::new().single_bracket_links(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .filter_map(|(e, _)| match e {
+                Start(Tag::Link { dest_url, title, .. }) => Some((dest_url, title)),
+                _ => None,
+            })
+            .collect();
+
+        assert!(events.contains(&("x".into(), "wiki".into())));
+        assert!(!events.iter().any(|(url, title)| &**title == "wiki" && &**url != "x"));
+    }
+
+    #[test]
+    fn link_in_html_comment() {
+        for s in ["<!-- [[x]] -->", "a <!-- [[x]] --> b", "<!--\n[[x]]\n-->\n\n[[y]]"] {
+            for bridge in [false, true] {
+                let wiki_options = WikiOptions::new().bridge_inline_html(bridge);
+                let targets: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.target).collect();
+                assert!(!targets.contains(&"x".into()), "{s:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn offset_by() {
+        let s = "# a\n\nsee [[b|c]]";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        let shifted: Vec<_> = ParserOffsetIter::new_ext_at(s, 3, Options::all(), true).offset_by(7).collect();
+
+        assert_eq!(events.len(), shifted.len());
+        for ((e, r), (shifted_e, shifted_r)) in events.into_iter().zip(shifted) {
+            assert_eq!(e, shifted_e);
+            assert_eq!(r.start + 10..r.end + 10, shifted_r);
+        }
+        let link = ParserOffsetIter::new_ext(s, Options::all(), true).offset_by(10).wikilinks().next().unwrap();
+        assert_eq!(link.alias_range, Some(23..24));
+    }
+
+    #[test]
+    fn display_separator() {
+        let s = "[[Show::real-page]] [[Show :: page|alias]] [[page]]";
+        let wiki_options = WikiOptions::new().display_separator("::").trim_spaces(true);
+        let links = links(s, wiki_options);
+
+        assert_eq!((&*links[0].target, &*links[0].display), ("real-page", "Show"));
+        assert_eq!(links[0].target_range, 8..17);
+        assert_eq!(links[0].alias_range, Some(2..6));
+        assert_eq!(links[0].link_type, WikiLinkType::Aliased);
+        assert_eq!((&*links[1].target, &*links[1].display), ("page", "alias"));
+        assert_eq!((&*links[2].target, &*links[2].display), ("page", "page"));
+    }
+
+    #[test]
+    fn links_on_separate_lines() {
+        // each line is its own text event, so the line breaks are kept between the links
+        let s = "[[a]]\n[[b]]\n[[c]]";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        let link = |t: &str, r: Range<usize>| [
+            (Start(Tag::Link{link_type: Inline, dest_url: t.to_string().into(), title: "wiki".into(), id: "".into()}), r.clone()),
+            (Text(t.to_string().into()), r.start + 2..r.end - 2),
+            (End(TagEnd::Link), r),
+        ];
+
+        let mut expected = vec![(Start(Tag::Paragraph), 0..17)];
+        expected.extend(link("a", 0..5));
+        expected.push((SoftBreak, 5..6));
+        expected.extend(link("b", 6..11));
+        expected.push((SoftBreak, 11..12));
+        expected.extend(link("c", 12..17));
+        expected.push((End(TagEnd::Paragraph), 0..17));
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn links_as_text() {
+        let s = "a [[b|c]] [[d]]";
+        let wiki_options = WikiOptions::new().links_as_text(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options).collect();
+
         assert_eq!(events, vec![
-                   (Start(Tag::Paragraph), 0..28),
-                   (Text("here is a wikilink: ".into()), 0..20),
-                   (Start(Tag::Link{link_type: Inline, dest_url: "link".into(), title: "wiki".into(), id: "".into()}), 
-                    20..28),
-                   (Text("link".into()), 22..26),
-                   (End(TagEnd::Link), 20..28),
-                   (End(TagEnd::Paragraph), 0..28),
+            (Start(Tag::Paragraph), 0..15),
+            (Text("a ".into()), 0..2),
+            (Text("c".into()), 6..7),
+            (Text(" ".into()), 9..10),
+            (Text("d".into()), 12..13),
+            (End(TagEnd::Paragraph), 0..15),
+        ]);
+
+        let wiki_options = Arc::new(WikiOptions::new().links_as_text(true));
+        let events: Vec<_> = WikiParser::new_with("[[a|b]]", 0..7, wiki_options).collect();
+        assert_eq!(events, vec![(Text("b".into()), 4..5)]);
+    }
+
+    /// asserts that every output of the parser emits the wikilinks of `s` in the same way
+    fn assert_same_outputs(s: &str, wiki_options: WikiOptions) {
+        let parser = || ParserOffsetIter::new_with(s, Options::all(), wiki_options.clone());
+        let expected: Vec<_> = parser().collect();
+        let expected_events: Vec<_> = expected.iter().map(|(e, _)| e.clone()).collect();
+
+        assert_eq!(parse_document(s, Options::all(), wiki_options.clone()).events, expected);
+        assert_eq!(parser().transclude(|_: &str| None, 4).collect::<Vec<_>>(), expected);
+        assert_eq!(parser().to_cmark().collect::<Vec<_>>(), expected_events);
+        assert_eq!(parser().to_standard_markdown().collect::<Vec<_>>(), expected_events);
+    }
+
+    #[test]
+    fn passthrough_in_every_output() {
+        let wiki_options = WikiOptions::new().passthrough_wikilinks(true).embeds(true);
+        assert_same_outputs("[[a|b]] and [[c]]\n\n![[note]]", wiki_options);
+    }
+
+    #[test]
+    fn links_as_text_in_every_output() {
+        let wiki_options = WikiOptions::new().links_as_text(true).embeds(true);
+        assert_same_outputs("[[a|b]] and [[c]]\n\n![[note]]", wiki_options);
+    }
+
+    #[test]
+    fn passthrough_wikilinks() {
+        let s = "see [[Page|the page]]";
+        let wiki_options = WikiOptions::new().passthrough_wikilinks(true);
+        let events: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options.clone()).collect();
+        assert_eq!(events, vec![
+            (Start(Tag::Paragraph), 0..21),
+            (Text("see ".into()), 0..4),
+            (Text("[[Page|the page]]".into()), 4..21),
+            (End(TagEnd::Paragraph), 0..21),
+        ]);
+
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options.clone());
+        parser.by_ref().for_each(drop);
+        assert_eq!(parser.stats().wikilinks, 1);
+
+        let links: Vec<_> = links(s, wiki_options).into_iter().map(|l| l.target).collect();
+        assert_eq!(links, vec!["Page".into()]);
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-normalization")]
+    fn nfc_targets() {
+        let s = "[[caf\u{e9}]] [[cafe\u{301}]]";
+        let wiki_options = WikiOptions::new().nfc_targets(true);
+        let links = links(s, wiki_options);
+
+        assert_eq!(links[0].target, links[1].target);
+        assert_eq!(links[1].display, "cafe\u{301}".into());
+    }
+
+    #[test]
+    fn contains_wikilinks() {
+        assert!(contains_wikilink("# Title\n\nsee [[a page|it]]."));
+        assert!(!contains_wikilink("# Title\n\nno [link](url) here"));
+        assert!(!contains_wikilink("[[]] [[ ]] [[|a]] [[a\nb]] [[a"));
+        // a cheap check: the code is not parsed
+        assert!(contains_wikilink("```\n[[a]]\n```"));
+    }
+
+    #[test]
+    fn link_after_code_span() {
+        let s = "`code`[[link]]";
+        let ranges: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .map(|(_, r)| r)
+            .collect();
+        // paragraph, code, start, text, end, paragraph
+        assert_eq!(ranges, vec![0..14, 0..6, 6..14, 8..12, 6..14, 0..14]);
+
+        let s = "`func()` see [[docs]]";
+        let (link, range) = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .map(|l| (l.target, l.range))
+            .next()
+            .unwrap();
+        assert_eq!(link, "docs".into());
+        assert_eq!(&s[range], "[[docs]]");
+    }
+
+    #[test]
+    fn block_indices() {
+        let s = "# [[a]]\n\nsome [[b]] and [[c]]\n\nno link\n\n[[d]]";
+        let blocks: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks_with_block()
+            .map(|(i, l)| (i, l.target))
+            .collect();
+
+        assert_eq!(blocks, vec![(0, "a".into()), (1, "b".into()), (1, "c".into()), (3, "d".into())]);
+    }
+
+    #[test]
+    fn padded_targets() {
+        let s = "[[page]] [[ page ]] [[ page|alias]] [[page |alias]]";
+        let wiki_options = WikiOptions::new().trim_spaces(true);
+        let padded: Vec<_> = links(s, wiki_options).into_iter().map(|l| (l.target, l.padded)).collect();
+
+        assert_eq!(padded, vec![
+                   ("page".into(), false),
+                   ("page".into(), true),
+                   ("page".into(), true),
+                   ("page".into(), true),
+        ]);
+
+        // the target is not trimmed, but the spaces are still noticed
+        let link = ParserOffsetIter::new_ext(s, Options::all(), true).wikilinks().nth(1).unwrap();
+        assert_eq!(link.target, " page ".into());
+        assert!(link.padded);
+    }
+
+    #[test]
+    fn parse_lines_of_range() {
+        let s = "# Title\nsome [[link]] here\nno [[link, é\n";
+        let link = s.find("link").unwrap();
+        let events: Vec<_> = WikiParser::new_at_lines(s, link..link + 2, WikiOptions::default()).collect();
+
+        assert_eq!(events, vec![
+                   (Text("some ".into()), 8..13),
+                   (Start(Tag::Link{link_type: Inline, dest_url: "link".into(), title: "wiki".into(), id: "".into()}), 13..21),
+                   (Text("link".into()), 15..19),
+                   (End(TagEnd::Link), 13..21),
+                   (Text(" here".into()), 21..26),
+        ]);
+
+        // in the middle of `é`, and past the end
+        let e = s.find('é').unwrap();
+        let events: Vec<_> = WikiParser::new_at_lines(s, e + 1..100, WikiOptions::default()).collect();
+        assert_eq!(events, vec![(Text("no ".into()), 27..30), (Text("[[link, é\n".into()), 30..41)]);
+    }
+
+    #[test]
+    fn pipe_range() {
+        let s = "[[a|b]] [[a]] [[a::b]]";
+        let pipes: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .wikilinks()
+            .map(|l| l.pipe_range)
+            .collect();
+        assert_eq!(pipes, vec![Some(3..4), None, None]);
+
+        let wiki_options = WikiOptions::new().separator("::");
+        let link = links(s, wiki_options).into_iter().nth(2).unwrap();
+        assert_eq!(link.pipe_range, Some(17..19));
+
+        let link = ParserOffsetIter::new_ext_at("[[a|b]]", 10, Options::all(), true).wikilinks().next().unwrap();
+        assert_eq!(link.pipe_range, Some(13..14));
+    }
+
+    #[test]
+    fn texts_of_different_blocks() {
+        let texts = |s| -> Vec<_> {
+            joined_events(s, Options::all())
+                .filter(|(e, _)| matches!(e, Text(_)))
+                .collect()
+        };
+        assert_eq!(texts("a [[b\n\nc]] d"), vec![
+                   (Text("a [[b".into()), 0..5),
+                   (Text("c]] d".into()), 7..12),
+        ]);
+        assert_eq!(texts("a [[b\nc]] d"), vec![
+                   (Text("a [[b".into()), 0..5),
+                   (Text("c]] d".into()), 6..11),
+        ]);
+
+        let links = ParserOffsetIter::new_ext("a [[b\n\nc]] d", Options::all(), true).wikilinks();
+        assert_eq!(links.count(), 0);
+    }
+
+    #[test]
+    fn max_wikilinks() {
+        let s = "[[a]] [[b]] [[c]]";
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), WikiOptions::new().max_wikilinks(2));
+        let texts: Vec<_> = parser
+            .by_ref()
+            .filter(|(e, _)| matches!(e, Text(_)))
+            .collect();
+
+        assert_eq!(texts, vec![
+                   (Text("a".into()), 2..3),
+                   (Text(" ".into()), 5..6),
+                   (Text("b".into()), 8..9),
+                   (Text(" [[c]]".into()), 11..17),
+        ]);
+        assert_eq!(parser.stats().wikilinks, 2);
+        assert_eq!(parser.stats().limit_reached_at, Some(11));
+
+        // the next paragraphs are not parsed either
+        let s = "[[a]] [[b]]\n\n[[c]] d";
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), WikiOptions::new().max_wikilinks(1));
+        let texts: Vec<_> = parser
+            .by_ref()
+            .filter(|(e, _)| matches!(e, Text(_)))
+            .collect();
+        assert_eq!(texts, vec![
+                   (Text("a".into()), 2..3),
+                   (Text(" [[b]]".into()), 5..11),
+                   (Text("[[c]] d".into()), 13..20),
+        ]);
+        assert_eq!(parser.stats().limit_reached_at, Some(5));
+
+        // reaching the limit without any link after it is not reported
+        let mut parser = ParserOffsetIter::new_with("[[a]] b", Options::all(), WikiOptions::new().max_wikilinks(1));
+        parser.by_ref().for_each(drop);
+        assert_eq!(parser.stats().limit_reached_at, None);
+
+        // even if the rest has brackets that are not a link
+        let s = "[[a]] [[b]]\n\nx [[ not a link";
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), WikiOptions::new().max_wikilinks(2));
+        parser.by_ref().for_each(drop);
+        assert_eq!(parser.stats().wikilinks, 2);
+        assert_eq!(parser.stats().limit_reached_at, None);
+
+        // but it is reported at the text with the first link
+        let s = "[[a]] [[b]]\n\nx [[ not a link\n\n[[c]]";
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), WikiOptions::new().max_wikilinks(2));
+        parser.by_ref().for_each(drop);
+        assert_eq!(parser.stats().limit_reached_at, Some(30));
+    }
+
+    #[test]
+    fn link_in_heading() {
+        let s = "## See [[Related]] {#see}\n\ntext";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .take(6)
+            .map(|(e, _)| e)
+            .collect();
+
+        assert_eq!(events, vec![
+                   Start(Tag::Heading { level: HeadingLevel::H2, id: Some("see".into()), classes: vec![], attrs: vec![] }),
+                   Text("See ".into()),
+                   Start(Tag::Link{link_type: Inline, dest_url: "Related".into(), title: "wiki".into(), id: "".into()}),
+                   Text("Related".into()),
+                   End(TagEnd::Link),
+                   End(TagEnd::Heading(HeadingLevel::H2)),
+        ]);
+    }
+
+    #[test]
+    fn link_to_heading_with_link() {
+        // the anchor of a heading is made from the text it displays
+        let s = "## See [[Related|the notes]]\n\n[[#See the notes]]";
+        let wiki_options = WikiOptions::new().fragment_style(FragmentStyle::GithubSlug);
+        let mut heading = String::new();
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options);
+        for (e, _) in parser.by_ref() {
+            match e {
+                Text(t) => heading.push_str(&t),
+                End(TagEnd::Heading(_)) => break,
+                _ => {}
+            }
+        }
+
+        let link = parser.wikilinks().next().unwrap();
+        assert_eq!(link.target, format!("#{}", wikilink::github_slug(&heading)).into());
+        assert_eq!(link.target, "#see-the-notes".into());
+    }
+
+    #[test]
+    fn blank_sources() {
+        for s in ["", "   ", "\n\n", " \t\r\n"] {
+            for wikilinks in [true, false] {
+                assert_eq!(ParserOffsetIter::new_ext(s, Options::all(), wikilinks).count(), 0);
+            }
+            let mut parser = ParserOffsetIter::new_ext(s, Options::all(), true);
+            assert!(parser.peek().is_none());
+            assert_eq!(parser.stats(), ParseStats::default());
+            assert_eq!(ParserOffsetIter::new_ext(s, Options::all(), true).wikilinks().count(), 0);
+        }
+
+        assert_eq!(ParserOffsetIter::new_ext("\u{a0}", Options::all(), true).count(), 3);
+
+        let mut parser = ParserOffsetIter::new_ext("", Options::all(), true);
+        parser.reset("[[a]]");
+        assert_eq!(parser.wikilinks().count(), 1);
+    }
+
+    #[test]
+    fn angle_brackets() {
+        // `<[[x]]>` is not an autolink, since `[[x]]` has no scheme
+        let s = "<[[x]]>";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        assert_eq!(events[1..6], vec![
+                   (Text("<".into()), 0..1),
+                   (Start(Tag::Link{link_type: Inline, dest_url: "x".into(), title: "wiki".into(), id: "".into()}), 1..6),
+                   (Text("x".into()), 3..4),
+                   (End(TagEnd::Link), 1..6),
+                   (Text(">".into()), 6..7),
+        ]);
+
+        // the text after an autolink is parsed from its first byte
+        let s = "<https://a.org>[[b]]";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true).collect();
+        assert_eq!(events[1..7], vec![
+                   (Start(Tag::Link{link_type: Autolink, dest_url: "https://a.org".into(), title: "".into(), id: "".into()}), 0..15),
+                   (Text("https://a.org".into()), 1..14),
+                   (End(TagEnd::Link), 0..15),
+                   (Start(Tag::Link{link_type: Inline, dest_url: "b".into(), title: "wiki".into(), id: "".into()}), 15..20),
+                   (Text("b".into()), 17..18),
+                   (End(TagEnd::Link), 15..20),
         ]);
     }
 
@@ -346,6 +3059,60 @@ mod tests {
                    )
     }
 
+    #[test]
+    fn link_after_thematic_break() {
+        // without a closing `---`, this is a horizontal rule, not a metadata block
+        for s in ["---\n[[link]]", "---\n\n[[link]]\n"] {
+            let links: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+                .wikilinks()
+                .collect();
+            assert_eq!(links.len(), 1);
+            assert_eq!(links[0].target, "link".into());
+        }
+    }
+
+
+    #[test]
+    fn context() {
+        let s = "---\nmetadata: test\n---\ntext";
+        let texts: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .with_context()
+            .filter(|(e, _, _)| matches!(e, Text(_)))
+            .map(|(_, _, c)| c)
+            .collect();
+
+        assert_eq!(texts, vec![
+                   Context { inside_metadata: true, inside_codeblock: false },
+                   Context { inside_metadata: false, inside_codeblock: false },
+        ]);
+    }
+
+    #[test]
+    fn context_around_code_block() {
+        // the events of a link are buffered, and all returned before the code block starts
+        let s = "a [[b]]\n\n```\n[[c]]\n```\n\n[[d]]";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .with_context()
+            .map(|(e, _, c)| (e, c.inside_codeblock))
+            .collect();
+
+        let code = |e: &Event| events.iter().find(|(x, _)| x == e).unwrap().1;
+        assert!(!code(&Start(Tag::Link{link_type: Inline, dest_url: "b".into(), title: "wiki".into(), id: "".into()})));
+        assert!(!code(&Text("b".into())));
+        assert!(!code(&End(TagEnd::Link)));
+        assert!(code(&Start(Tag::CodeBlock(CodeBlockKind::Fenced("".into())))));
+        assert!(code(&Text("[[c]]\n".into())));
+        assert!(code(&End(TagEnd::CodeBlock)));
+        assert!(!code(&Text("d".into())));
+
+        let mut parser = ParserOffsetIter::new_ext(s, Options::all(), true);
+        while parser.next().is_some_and(|(e, _)| e != End(TagEnd::Link)) {}
+        assert!(!parser.inside_codeblock);
+        while parser.next().is_some_and(|(e, _)| e != Text("[[c]]\n".into())) {}
+        assert!(parser.inside_codeblock);
+        while parser.next().is_some_and(|(e, _)| e != End(TagEnd::CodeBlock)) {}
+        assert!(!parser.inside_codeblock);
+    }
 
     #[test]
     fn parse_alias(){
@@ -474,6 +3241,48 @@ mod tests {
         ])
     }
 
+    /// a sample of the examples of the CommonMark spec
+    const SPEC_EXAMPLES: &[&str] = &[
+        "\tfoo\tbaz\t\tbim\n",
+        "- `one\n- two`\n",
+        "***\n---\n___\n",
+        "# foo\n## foo\n### foo\n",
+        "Foo *bar*\n=========\n",
+        "    a simple\n      indented code block\n",
+        "```\n<\n >\n```\n",
+        "<table><tr><td>\n<pre>\n**Hello**,\n\n_world_.\n</pre>\n</td></tr></table>\n",
+        "[foo]: /url \"title\"\n\n[foo]\n",
+        "aaa\n\nbbb\n",
+        "> # Foo\n> bar\n> baz\n",
+        "1.  A paragraph\n    with two lines.\n\n        indented code\n\n    > A block quote.\n",
+        r#"\!\"\#\$\%\&\'\(\)\*\+\,\-\.\/\:\;\<\=\>\?\@\[\\\]\^\_\`\{\|\}\~"#,
+        "&nbsp; &amp; &copy; &AElig; &Dcaron;\n",
+        "`` foo ` bar ``\n",
+        "*foo`*`\n",
+        "**foo \"*bar*\" foo**\n",
+        "[link](/uri \"title\")\n",
+        "[link [foo [bar]]](/uri)\n",
+        "![foo *bar*][]\n\n[foo *bar*]: train.jpg \"train & tracks\"\n",
+        "<http://foo.bar.baz>\n",
+        "foo <!-- this is a\ncomment - with hyphen -->\n",
+        "foo  \nbaz\n",
+        "foo\\\nbaz\n",
+        "[[not a link]] with [brackets] and ]] stray\n",
+    ];
+
+    #[test]
+    fn passthrough_matches_upstream() {
+        for s in SPEC_EXAMPLES {
+            let upstream: Vec<_> = _Parser::new_ext(s, Options::all())
+                .into_offset_iter()
+                .collect();
+            let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), false)
+                .collect();
+
+            assert_eq!(events, upstream, "divergence on {s:?}");
+        }
+    }
+
     #[test]
     fn table(){
         // this is mainly a no-regression test.
@@ -489,4 +3298,44 @@ mod tests {
         assert_eq!(ParserOffsetIter::new_ext(s, Options::all(), true).count(),
                 43);
     }
+
+    #[test]
+    fn link_in_table_cell() {
+        let s = "| name | link |\n| --- | --- |\n| a | [[note]] |\n| **c** | [[d]] |\n";
+        let events: Vec<_> = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .filter(|(e, _)| matches!(e, Start(Tag::Link { .. }) | Start(Tag::TableCell)))
+            .map(|(e, r)| match e {
+                Start(Tag::Link { dest_url, .. }) => (Some(dest_url), r),
+                _ => (None, r),
+            })
+            .collect();
+
+        let links: Vec<_> = events.iter().filter(|(t, _)| t.is_some()).cloned().collect();
+        assert_eq!(links, vec![
+                   (Some("note".into()), 36..44),
+                   (Some("d".into()), 57..62),
+        ]);
+        // every link is inside its cell
+        let cells: Vec<_> = events.iter().filter(|(t, _)| t.is_none()).map(|(_, r)| r.clone()).collect();
+        assert!(cells.iter().any(|c| c.start <= 36 && 44 <= c.end));
+        assert!(cells.iter().any(|c| c.start <= 57 && 62 <= c.end));
+    }
+
+    #[test]
+    fn options_debug() {
+        let wiki_options = WikiOptions::new().embeds(true).transform(|t: &str| t.to_uppercase());
+        let debug = format!("{wiki_options:?}");
+
+        assert!(debug.starts_with("WikiOptions { external_links: false, trim_spaces: false, partial_input: false, embeds: true,"));
+        assert!(debug.ends_with(", .. }"));
+    }
+
+    #[test]
+    fn options_are_send() {
+        fn assert_send<T: Send + Sync>() {}
+
+        assert_send::<WikiOptions>();
+        assert_send::<WikiLink<'static>>();
+        assert_send::<WikiParser<'static, 'static>>();
+    }
 }
@@ -0,0 +1,78 @@
+use crate::{Item, Options, ParserOffsetIter, WikiLink};
+use pulldown_cmark::{Event, TagEnd};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A piece of a document, without its markdown structure.
+///
+/// Returned by [`parse_to_nodes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Node<'a> {
+    /// the text between the wikilinks, with the line breaks and the ends of blocks as `\n`
+    Text(String),
+    Wiki(WikiLink<'a>),
+}
+
+/// Parses `source` into the text and the wikilinks it contains, in order,
+/// for example to render a preview of a search result.
+///
+/// The block structure and the formatting are dropped, only the text
+/// and the inline code are kept. Consecutive texts are merged,
+/// and separated by a `\n` if they are in different blocks.
+pub fn parse_to_nodes(source: &str) -> Vec<Node<'_>> {
+    let mut parser = ParserOffsetIter::new_ext(source, Options::empty(), true);
+    let mut nodes = Vec::new();
+    fn push_text(nodes: &mut Vec<Node<'_>>, text: &str) {
+        match nodes.last_mut() {
+            Some(Node::Text(t)) => t.push_str(text),
+            _ => nodes.push(Node::Text(text.into())),
+        }
+    }
+    // a block ended, and its content must be separated from the next one
+    let mut after_block = false;
+    while let Some(item) = parser.next_item() {
+        if after_block && !matches!(item, Item::Event(Event::Start(_) | Event::End(_), _)) {
+            after_block = false;
+            if !nodes.is_empty() {
+                push_text(&mut nodes, "\n");
+            }
+        }
+        match item {
+            Item::Link(l) => nodes.push(Node::Wiki(l)),
+            Item::Event(Event::Text(t) | Event::Code(t), _) => push_text(&mut nodes, &t),
+            Item::Event(Event::SoftBreak | Event::HardBreak, _) => push_text(&mut nodes, "\n"),
+            Item::Event(Event::End(t), _) => {
+                use TagEnd::*;
+                after_block |= !matches!(t, Emphasis | Strong | Strikethrough | Link | Image);
+            }
+            Item::Event(_, _) => {}
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_and_link() {
+        let nodes = parse_to_nodes("hello [[world]]!");
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0], Node::Text("hello ".into()));
+        assert!(matches!(&nodes[1], Node::Wiki(l) if l.target == "world".into()));
+        assert_eq!(nodes[2], Node::Text("!".into()));
+    }
+
+    #[test]
+    fn texts_of_blocks() {
+        let nodes = parse_to_nodes("hello\n\nworld [[a]]");
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0], Node::Text("hello\nworld ".into()));
+        assert!(matches!(&nodes[1], Node::Wiki(l) if l.target == "a".into()));
+
+        assert_eq!(parse_to_nodes("# Title\nbody"), vec![Node::Text("Title\nbody".into())]);
+    }
+}
@@ -0,0 +1,534 @@
+use crate::{TargetTransform, WikiLinkProcessor};
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt;
+
+/// Options controlling how wikilinks are recognized and emitted.
+///
+/// Every option is disabled by default, so that
+/// `WikiOptions::default()` behaves exactly like [`ParserOffsetIter::new_ext`]
+///
+/// [`ParserOffsetIter::new_ext`]: crate::ParserOffsetIter::new_ext
+#[derive(Clone, Default)]
+pub struct WikiOptions {
+    pub(crate) external_links: bool,
+    pub(crate) trim_spaces: bool,
+    pub(crate) partial_input: bool,
+    pub(crate) embeds: bool,
+    pub(crate) raw_targets: bool,
+    pub(crate) transform: Option<Arc<dyn TargetTransform + Send + Sync>>,
+    pub(crate) strict_pipes: bool,
+    pub(crate) separator: Option<Arc<str>>,
+    pub(crate) greedy_close: bool,
+    pub(crate) image_extensions: Option<Arc<[Box<str>]>>,
+    pub(crate) newline_terminates_link: bool,
+    pub(crate) allowed_chars: Option<Arc<dyn Fn(char) -> bool + Send + Sync>>,
+    pub(crate) sanitize_targets: bool,
+    pub(crate) display_mode: DisplayMode,
+    pub(crate) title_case_display: bool,
+    pub(crate) bridge_inline_html: bool,
+    pub(crate) target_prefix: String,
+    pub(crate) target_suffix: String,
+    pub(crate) strip_chars: Option<Arc<[char]>>,
+    pub(crate) split_fragments: bool,
+    pub(crate) split_block_refs: bool,
+    pub(crate) current_dir: Option<Arc<str>>,
+    pub(crate) escaped_brackets: bool,
+    pub(crate) generate_ids: bool,
+    pub(crate) space_replacement: Option<char>,
+    pub(crate) roam_block_refs: bool,
+    pub(crate) fragment_style: FragmentStyle,
+    pub(crate) delimiters: Option<Arc<[Delimiter]>>,
+    pub(crate) reject_path_traversal: bool,
+    pub(crate) folder_index: Option<Arc<str>>,
+    pub(crate) unquote_targets: bool,
+    pub(crate) single_bracket_links: bool,
+    pub(crate) display_separator: Option<Arc<str>>,
+    pub(crate) links_as_text: bool,
+    pub(crate) passthrough_wikilinks: bool,
+    pub(crate) max_wikilinks: Option<usize>,
+    pub(crate) processor: Option<Arc<dyn WikiLinkProcessor + Send + Sync>>,
+    #[cfg(feature = "unicode-normalization")]
+    pub(crate) nfc_targets: bool,
+}
+
+/// the hooks, like the [`transform`](WikiOptions::transform), are not shown
+impl fmt::Debug for WikiOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = f.debug_struct("WikiOptions");
+        s.field("external_links", &self.external_links)
+            .field("trim_spaces", &self.trim_spaces)
+            .field("partial_input", &self.partial_input)
+            .field("embeds", &self.embeds)
+            .field("raw_targets", &self.raw_targets)
+            .field("strict_pipes", &self.strict_pipes)
+            .field("separator", &self.separator)
+            .field("greedy_close", &self.greedy_close)
+            .field("image_extensions", &self.image_extensions)
+            .field("newline_terminates_link", &self.newline_terminates_link)
+            .field("sanitize_targets", &self.sanitize_targets)
+            .field("display_mode", &self.display_mode)
+            .field("title_case_display", &self.title_case_display)
+            .field("bridge_inline_html", &self.bridge_inline_html)
+            .field("target_prefix", &self.target_prefix)
+            .field("target_suffix", &self.target_suffix)
+            .field("strip_chars", &self.strip_chars)
+            .field("split_fragments", &self.split_fragments)
+            .field("split_block_refs", &self.split_block_refs)
+            .field("current_dir", &self.current_dir)
+            .field("escaped_brackets", &self.escaped_brackets)
+            .field("generate_ids", &self.generate_ids)
+            .field("space_replacement", &self.space_replacement)
+            .field("roam_block_refs", &self.roam_block_refs)
+            .field("fragment_style", &self.fragment_style)
+            .field("delimiters", &self.delimiters)
+            .field("reject_path_traversal", &self.reject_path_traversal)
+            .field("folder_index", &self.folder_index)
+            .field("unquote_targets", &self.unquote_targets)
+            .field("single_bracket_links", &self.single_bracket_links)
+            .field("display_separator", &self.display_separator)
+            .field("links_as_text", &self.links_as_text)
+            .field("passthrough_wikilinks", &self.passthrough_wikilinks)
+            .field("max_wikilinks", &self.max_wikilinks);
+        #[cfg(feature = "unicode-normalization")]
+        s.field("nfc_targets", &self.nfc_targets);
+        s.finish_non_exhaustive()
+    }
+}
+
+/// A pair of extra delimiters, like `{{` and `}}`, see [`WikiOptions::delimiters`]
+#[derive(Debug)]
+pub(crate) struct Delimiter {
+    pub(crate) open: Box<str>,
+    pub(crate) close: Box<str>,
+    pub(crate) kind: Arc<str>,
+}
+
+impl Delimiter {
+    pub(crate) fn new(open: &str, close: &str, kind: &str) -> Self {
+        Self { open: open.into(), close: close.into(), kind: kind.into() }
+    }
+}
+
+/// The characters that can't be seen, but break the match of a target,
+/// like the zero width space. See [`WikiOptions::strip_chars`]
+pub const INVISIBLE_CHARS: &[char] = &[
+    '\u{00AD}', // soft hyphen
+    '\u{200B}', // zero width space
+    '\u{200C}', // zero width non-joiner
+    '\u{200D}', // zero width joiner
+    '\u{200E}', // left-to-right mark
+    '\u{200F}', // right-to-left mark
+    '\u{2060}', // word joiner
+    '\u{FEFF}', // zero width no-break space
+];
+
+/// What is displayed by a link without an alias, like `[[a/b/c]]`,
+/// see [`WikiOptions::display_mode`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DisplayMode {
+    /// the alias if there is one, even if it is empty like in `[[a/b/c|]]`,
+    /// otherwise the whole target `a/b/c`. A blank alias, like in `[[a/b/c| ]]`,
+    /// displays the target
+    #[default]
+    AliasOrTarget,
+    /// the whole target `a/b/c`, when the alias is missing or blank, like in `[[a/b/c| ]]`
+    FullTarget,
+    /// the last segment `c` of the target, when the alias is missing or blank
+    LastSegment,
+}
+
+/// How the fragment of a target, like `Heading` in `[[Page#Heading]]`,
+/// is written in the link, see [`WikiOptions::fragment_style`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FragmentStyle {
+    /// the fragment is kept as it is written
+    #[default]
+    AsWritten,
+    /// the fragment is the id of the heading on GitHub,
+    /// so that `My Heading!` becomes `my-heading`
+    GithubSlug,
+}
+
+/// The extensions of the embeds that are images, unless
+/// [`WikiOptions::image_extensions`] is used
+pub const DEFAULT_IMAGE_EXTENSIONS: &[&str] =
+    &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "avif"];
+
+impl WikiOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// when enabled, a target with an explicit scheme, like
+    /// `[[https://example.com|label]]` or `[[mailto:a@b.com]]`,
+    /// becomes a normal markdown link, without the `wiki` marker
+    pub fn external_links(mut self, enable: bool) -> Self {
+        self.external_links = enable;
+        self
+    }
+
+    /// when enabled, the whitespace around the target and the alias is ignored,
+    /// so that `[[target | alias ]]` links to `target` and displays `alias`
+    pub fn trim_spaces(mut self, enable: bool) -> Self {
+        self.trim_spaces = enable;
+        self
+    }
+
+    /// when enabled, the input is expected to be incomplete, as in a live preview.
+    /// An unterminated `[[` stops at the next `[[`, and is emitted as its own text event,
+    /// so that `[[par and [[b]]` displays `[[par and ` followed by a link to `b`
+    pub fn partial_input(mut self, enable: bool) -> Self {
+        self.partial_input = enable;
+        self
+    }
+
+    /// when enabled, `![[target]]` is an embed, emitted as an image
+    pub fn embeds(mut self, enable: bool) -> Self {
+        self.embeds = enable;
+        self
+    }
+
+    /// when enabled, the target is the byte-exact text between `[[` and either `|` or `]]`.
+    /// No other option transforms it: no trimming, no encoding.
+    pub fn raw_targets(mut self, enable: bool) -> Self {
+        self.raw_targets = enable;
+        self
+    }
+
+    /// rewrites the target of every wikilink with `transform`.
+    /// Targets of external links and raw targets are kept as they are.
+    pub fn transform(mut self, transform: impl TargetTransform + Send + Sync + 'static) -> Self {
+        self.transform = Some(Arc::new(transform));
+        self
+    }
+
+    /// passes every wikilink to `processor`, after all the other options,
+    /// to change it, mark it as broken, or drop it
+    pub fn processor(mut self, processor: impl WikiLinkProcessor + Send + Sync + 'static) -> Self {
+        self.processor = Some(Arc::new(processor));
+        self
+    }
+
+    /// when enabled, a double pipe after the target, like in `[[a||b]]`,
+    /// is considered a mistake and the link is kept as text.
+    /// Otherwise the first pipe is the separator, and the alias is `|b`
+    pub fn strict_pipes(mut self, enable: bool) -> Self {
+        self.strict_pipes = enable;
+        self
+    }
+
+    /// uses `separator` instead of `|` between the target and the alias,
+    /// so that with `::`, `[[a::b]]` links to `a` and displays `b`.
+    ///
+    /// # Panics
+    /// if `separator` is empty
+    pub fn separator(mut self, separator: &str) -> Self {
+        assert!(!separator.is_empty(), "the alias separator can't be empty");
+        self.separator = Some(separator.into());
+        for d in self.delimiters.iter().flat_map(|d| d.iter()) {
+            self.check_delimiter(&d.open);
+            self.check_delimiter(&d.close);
+        }
+        self
+    }
+
+    /// panics if `delim` can't be one of the [`delimiters`](Self::delimiters)
+    fn check_delimiter(&self, delim: &str) {
+        let separator = self.separator.as_deref().unwrap_or("|");
+        assert!(!delim.is_empty(), "a delimiter can't be empty");
+        assert!(!delim.starts_with(['[', ']']), "the delimiter {delim:?} can't start with a bracket");
+        assert!(!delim.starts_with(separator), "the delimiter {delim:?} can't start with the separator");
+    }
+
+    /// when enabled, a wikilink is closed by the last `]]` of the line
+    /// (before the next `[[`), so that `[[a]]b]]` links to `a]]b`.
+    ///
+    /// Beware that a stray `]]` later on the line then becomes part of the link.
+    pub fn greedy_close(mut self, enable: bool) -> Self {
+        self.greedy_close = enable;
+        self
+    }
+
+    /// the extensions, without the dot, of the embeds that are emitted as images.
+    /// Other embeds, like `![[Note]]`, are transclusions,
+    /// emitted as links with the [`TRANSCLUSION_MARKER`](crate::TRANSCLUSION_MARKER).
+    ///
+    /// The comparison ignores the ASCII case.
+    /// Defaults to [`DEFAULT_IMAGE_EXTENSIONS`]
+    pub fn image_extensions(mut self, extensions: &[&str]) -> Self {
+        self.image_extensions = Some(extensions.iter().map(|&e| e.into()).collect());
+        self
+    }
+
+    /// when enabled, a newline before the closing `]]` means that the author
+    /// forgot to close the link, so `[[a\nb]]` is kept as text.
+    ///
+    /// It only changes a [`WikiParser`](crate::WikiParser) made with
+    /// [`new_at_lines`](crate::WikiParser::new_at_lines) over several lines:
+    /// the markdown parser already splits the text at each line break,
+    /// so the [`ParserOffsetIter`](crate::ParserOffsetIter) never finds such a link
+    pub fn newline_terminates_link(mut self, enable: bool) -> Self {
+        self.newline_terminates_link = enable;
+        self
+    }
+
+    /// only accepts targets made of the characters for which `allowed` is true.
+    /// A link with another character is kept as text,
+    /// unless [`sanitize_targets`](Self::sanitize_targets) is enabled
+    pub fn allowed_chars(mut self, allowed: impl Fn(char) -> bool + Send + Sync + 'static) -> Self {
+        self.allowed_chars = Some(Arc::new(allowed));
+        self
+    }
+
+    /// when enabled, the characters that are not [allowed](Self::allowed_chars)
+    /// are dropped from the target, instead of the link being kept as text.
+    /// Raw targets are never sanitized
+    pub fn sanitize_targets(mut self, enable: bool) -> Self {
+        self.sanitize_targets = enable;
+        self
+    }
+
+    /// chooses what is displayed by the links without an alias
+    pub fn display_mode(mut self, mode: DisplayMode) -> Self {
+        self.display_mode = mode;
+        self
+    }
+
+    /// when enabled, a link without an alias displays its target with
+    /// the first letter of each word in uppercase, so `[[my note]]` displays `My Note`.
+    /// The other letters are kept, so acronyms stay as they are
+    pub fn title_case_display(mut self, enable: bool) -> Self {
+        self.title_case_display = enable;
+        self
+    }
+
+    /// when enabled, the inline html after a `[[` that is not closed yet
+    /// is part of the text where wikilinks are searched, so `[[a<br>b]]` links to `a<br>b`.
+    ///
+    /// Otherwise the html splits the text, and such a link is kept as text and html.
+    /// Beware that if the `[[` is never closed, the html is emitted as text
+    pub fn bridge_inline_html(mut self, enable: bool) -> Self {
+        self.bridge_inline_html = enable;
+        self
+    }
+
+    /// adds `prefix` before the target of every wikilink,
+    /// so that with `/wiki/`, `[[Note]]` links to `/wiki/Note`.
+    ///
+    /// It is applied after the [`transform`](Self::transform), and not to
+    /// external links, raw targets, or links to the same page like `[[#Heading]]`
+    pub fn target_prefix(mut self, prefix: &str) -> Self {
+        self.target_prefix = prefix.into();
+        self
+    }
+
+    /// adds `suffix` after the page of every wikilink, before the `#`,
+    /// so that with `.html`, `[[Note#Heading]]` links to `Note.html#Heading`.
+    ///
+    /// It is applied to the same links as [`target_prefix`](Self::target_prefix)
+    pub fn target_suffix(mut self, suffix: &str) -> Self {
+        self.target_suffix = suffix.into();
+        self
+    }
+
+    /// removes `chars` from the targets, usually [`INVISIBLE_CHARS`],
+    /// that come from pasted content and prevent a link from resolving.
+    /// The ranges are still those of the source, and raw targets are kept as they are.
+    ///
+    /// The number of removed characters is in [`ParseStats::stripped_chars`](crate::ParseStats)
+    pub fn strip_chars(mut self, chars: &[char]) -> Self {
+        self.strip_chars = Some(chars.into());
+        self
+    }
+
+    /// when enabled, the [parts](crate::TargetParts) of a target are split at the `#`,
+    /// so that `[[Page#Heading]]` has the page `Page` and the fragment `Heading`
+    pub fn split_fragments(mut self, enable: bool) -> Self {
+        self.split_fragments = enable;
+        self
+    }
+
+    /// when enabled, the [parts](crate::TargetParts) of a target are split at the `#^`
+    /// or a leading `^`, so that `[[Page#^id]]` has the page `Page` and the block `id`
+    pub fn split_block_refs(mut self, enable: bool) -> Self {
+        self.split_block_refs = enable;
+        self
+    }
+
+    /// resolves the relative targets, that start with `./` or `../`,
+    /// against `dir`, the folder of the note being parsed.
+    /// With `notes/sub`, `[[../x]]` links to `notes/x` and `[[./y]]` to `notes/sub/y`.
+    ///
+    /// The paths are separated by `/`, and can't go above the root of `dir`
+    pub fn current_dir(mut self, dir: &str) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// when enabled, `\]]` doesn't close a wikilink, and is displayed as `]]`,
+    /// so that `[[a\]]b]]` links to `a]]b`
+    pub fn escaped_brackets(mut self, enable: bool) -> Self {
+        self.escaped_brackets = enable;
+        self
+    }
+
+    /// when enabled, the id of each link is derived from its target,
+    /// so that `[[My Note]]` has the id `wikilink-my-note`.
+    /// Two links to the same target have the same id
+    pub fn generate_ids(mut self, enable: bool) -> Self {
+        self.generate_ids = enable;
+        self
+    }
+
+    /// replaces the spaces of the targets by `c`, like MediaWiki,
+    /// so that with `_`, `[[My Note]]` links to `My_Note`. The display keeps the spaces.
+    ///
+    /// It is applied after the [`transform`](Self::transform) and before the
+    /// [`target_prefix`](Self::target_prefix), but not to external links or raw targets
+    pub fn space_replacement(mut self, c: char) -> Self {
+        self.space_replacement = Some(c);
+        self
+    }
+
+    /// when enabled, `((uid))` is a block ref as in Roam, alongside the wikilinks.
+    /// It is emitted as a link to `uid`, with the [`ROAM_BLOCK_REF_MARKER`](crate::ROAM_BLOCK_REF_MARKER).
+    ///
+    /// The uid is only made of alphanumeric characters, `-` and `_`,
+    /// so that `((some text))` is kept as text
+    pub fn roam_block_refs(mut self, enable: bool) -> Self {
+        self.roam_block_refs = enable;
+        self
+    }
+
+    /// how the fragment of a target is written, so that with [`FragmentStyle::GithubSlug`],
+    /// `[[Page#My Heading]]` links to `Page#my-heading`.
+    ///
+    /// It only changes the headings, not the block refs like `[[Page#^id]]`,
+    /// and is applied before the [`transform`](Self::transform)
+    pub fn fragment_style(mut self, style: FragmentStyle) -> Self {
+        self.fragment_style = style;
+        self
+    }
+
+    /// recognizes each `(open, close, kind)` like `("{{", "}}", "template")`
+    /// as delimiters alongside `[[` and `]]`, so that `{{a|b}}` is parsed as a wikilink
+    /// of the given [`kind`](crate::WikiLink::kind).
+    /// It is emitted as a link with `kind` as its title, instead of the
+    /// [`WIKI_MARKER`](crate::WIKI_MARKER).
+    ///
+    /// The target and the alias of these links are only trimmed, without any other option.
+    ///
+    /// # Panics
+    /// if a delimiter is empty, or starts with a bracket or the [separator](Self::separator)
+    pub fn delimiters(mut self, delimiters: &[(&str, &str, &str)]) -> Self {
+        for &(open, close, _) in delimiters {
+            self.check_delimiter(open);
+            self.check_delimiter(close);
+        }
+        self.delimiters = Some(delimiters.iter().map(|&(o, c, k)| Delimiter::new(o, c, k)).collect());
+        self
+    }
+
+    /// when enabled, a wikilink whose target has a `..` segment, like `[[../../etc/passwd]]`,
+    /// is kept as text, for the apps that read the targets as paths.
+    ///
+    /// These links are counted in [`ParseStats::path_traversals`](crate::ParseStats) anyway
+    pub fn reject_path_traversal(mut self, enable: bool) -> Self {
+        self.reject_path_traversal = enable;
+        self
+    }
+
+    /// a target that ends with a `/` is a folder, and links to its index note `name`,
+    /// so that with `index`, `[[folder/]]` links to `folder/index`.
+    /// The folder is displayed without the trailing `/`.
+    ///
+    /// It is applied before the [`current_dir`](Self::current_dir),
+    /// but not to external links or raw targets
+    pub fn folder_index(mut self, name: &str) -> Self {
+        self.folder_index = Some(name.into());
+        self
+    }
+
+    /// when enabled, the quotes around a target are removed, so that
+    /// `[["My Note"]]` and `[['My Note']]` link to `My Note` and display it.
+    /// Unbalanced quotes, like in `[["a]]`, are kept
+    pub fn unquote_targets(mut self, enable: bool) -> Self {
+        self.unquote_targets = enable;
+        self
+    }
+
+    /// when enabled, `[Page]` is also a wikilink to `Page`, as in some older wikis,
+    /// unless it is a part of a markdown link like `[text](url)` or `[text][ref]`,
+    /// a definition like `[ref]: url`, or a footnote like `[^1]`.
+    ///
+    /// The target of these links is only trimmed, without any other option
+    pub fn single_bracket_links(mut self, enable: bool) -> Self {
+        self.single_bracket_links = enable;
+        self
+    }
+
+    /// in the target, `separator` comes after the text to display,
+    /// so that with `::`, `[[Show::real-page]]` links to `real-page` and displays `Show`.
+    /// An alias after the pipe is displayed instead, and raw targets are not split.
+    ///
+    /// # Panics
+    /// if `separator` is empty
+    pub fn display_separator(mut self, separator: &str) -> Self {
+        assert!(!separator.is_empty(), "the display separator can't be empty");
+        self.display_separator = Some(separator.into());
+        self
+    }
+
+    /// when enabled, a wikilink is emitted as the text it displays, without a link,
+    /// so that `[[a|b]]` is only `Text("b")`, for plain text excerpts.
+    /// The text is not merged with the texts around it
+    pub fn links_as_text(mut self, enable: bool) -> Self {
+        self.links_as_text = enable;
+        self
+    }
+
+    /// when enabled, a wikilink is emitted as the text of its source, like `[[a|b]]`,
+    /// so that a renderer that knows wikilinks, like the one of GitHub wikis, handles it.
+    /// The wikilinks are still parsed, for [`wikilinks`](crate::ParserOffsetIter::wikilinks)
+    /// and the stats
+    pub fn passthrough_wikilinks(mut self, enable: bool) -> Self {
+        self.passthrough_wikilinks = enable;
+        self
+    }
+
+    /// after `max` wikilinks in a document, the rest of it is not parsed for wikilinks
+    /// and is emitted as text, as a safety limit for untrusted input.
+    /// Where it stopped is in [`ParseStats::limit_reached_at`](crate::ParseStats::limit_reached_at),
+    /// and [`parse_document`](crate::parse_document) reports it as a
+    /// [`DiagnosticKind::TooManyWikilinks`](crate::DiagnosticKind::TooManyWikilinks)
+    pub fn max_wikilinks(mut self, max: usize) -> Self {
+        self.max_wikilinks = Some(max);
+        self
+    }
+
+    /// when enabled, the targets are in the Unicode normalization form C,
+    /// so that a composed `é` and an `e` followed by a combining accent link to the same page.
+    /// The display is kept as it is written, and raw targets are not changed
+    #[cfg(feature = "unicode-normalization")]
+    pub fn nfc_targets(mut self, enable: bool) -> Self {
+        self.nfc_targets = enable;
+        self
+    }
+
+    /// `self.is_image(target)` is true if the page of `target`,
+    /// ie what comes before the `#`, ends with an image extension
+    pub(crate) fn is_image(&self, target: &str) -> bool {
+        let page = target.split('#').next().unwrap_or_default();
+        let extension = match page.rsplit_once('.') {
+            Some((_, e)) if !e.contains('/') => e,
+            _ => return false,
+        };
+        match &self.image_extensions {
+            Some(exts) => exts.iter().any(|e| e.eq_ignore_ascii_case(extension)),
+            None => DEFAULT_IMAGE_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(extension)),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::ParserOffsetIter;
+use pulldown_cmark::Event;
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A position in the source, as shown to authors.
+///
+/// Both the line and the column start at 1,
+/// and the column counts characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Converts byte offsets into line/column positions.
+///
+/// The start of each line is computed once, so that each lookup
+/// only has to scan the line it falls into.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = core::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { source, line_starts }
+    }
+
+    /// the position of the byte `offset` of the source
+    pub fn position(&self, offset: usize) -> Position {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(l) => l,
+            Err(l) => l - 1,
+        };
+        let line_start = self.line_starts[line];
+        let col = self.source[line_start..offset].chars().count();
+        Position { line: line + 1, col: col + 1 }
+    }
+
+    /// the start and end positions of `range`
+    pub fn positions(&self, range: Range<usize>) -> (Position, Position) {
+        (self.position(range.start), self.position(range.end))
+    }
+}
+
+/// The text around `range`, up to `radius` bytes on each side, to show
+/// a link in an error message.
+///
+/// The context stops at the end of the sentence (or line) of the link,
+/// and doesn't end in the middle of a word. It is trimmed.
+pub fn context_around(source: &str, range: Range<usize>, radius: usize) -> &str {
+    let is_end = |c: char| matches!(c, '.' | '!' | '?' | '\n');
+
+    let mut start = range.start.saturating_sub(radius);
+    while !source.is_char_boundary(start) {
+        start += 1;
+    }
+    let before = &source[start..range.start];
+    if let Some(i) = before.rfind(is_end) {
+        start += i + 1;
+    } else if start > 0 && !source[..start].ends_with(char::is_whitespace) {
+        start += before.find(char::is_whitespace).unwrap_or(before.len());
+    }
+
+    let mut end = range.end.saturating_add(radius).min(source.len());
+    while !source.is_char_boundary(end) {
+        end -= 1;
+    }
+    let after = &source[range.end..end];
+    if let Some(i) = after.find(is_end) {
+        // the punctuation is part of the sentence
+        end = range.end + i + 1;
+    } else if end < source.len() && !source[end..].starts_with(char::is_whitespace) {
+        end = range.end + after.rfind(char::is_whitespace).unwrap_or(0);
+    }
+
+    source[start..end].trim()
+}
+
+/// An iterator over `(Event, Range, (Position, Position))` triples.
+///
+/// Returned by [`ParserOffsetIter::with_positions`]
+pub struct WithPositions<'a, 'b> {
+    pub(crate) parser: ParserOffsetIter<'a, 'b>,
+    pub(crate) index: LineIndex<'a>,
+}
+
+impl<'a, 'b> Iterator for WithPositions<'a, 'b> {
+    type Item = (Event<'a>, Range<usize>, (Position, Position));
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, r) = self.parser.next()?;
+        // positions are relative to the source that is parsed, not to the base offset
+        let base = self.parser.base_offset;
+        let positions = self.index.positions(r.start - base..r.end - base);
+        Some((e, r, positions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, Tag};
+
+    #[test]
+    fn link_on_third_line() {
+        let s = "first line\n\nthé [[link]]";
+        let (_, _, positions) = ParserOffsetIter::new_ext(s, Options::all(), true)
+            .with_positions()
+            .find(|(e, _, _)| matches!(e, Event::Start(Tag::Link { .. })))
+            .unwrap();
+
+        assert_eq!(positions, (Position { line: 3, col: 5 }, Position { line: 3, col: 13 }));
+    }
+
+    #[test]
+    fn context() {
+        let s = "First. Then a [[link]] in the middle of a sentence. End";
+        let link = s.find("[[").unwrap()..s.find("]]").unwrap() + 2;
+
+        assert_eq!(context_around(s, link.clone(), 100), "Then a [[link]] in the middle of a sentence.");
+        // words are not cut
+        assert_eq!(context_around(s, link.clone(), 10), "Then a [[link]] in the");
+        assert_eq!(context_around(s, link, 5), "a [[link]] in");
+    }
+
+    #[test]
+    fn context_at_the_edges() {
+        let s = "[[a]] b";
+        assert_eq!(context_around(s, 0..5, 10), "[[a]] b");
+        assert_eq!(context_around(s, 0..5, 0), "[[a]]");
+
+        let s = "é [[a]]";
+        assert_eq!(context_around(s, 3..8, 2), "[[a]]");
+        assert_eq!(context_around(s, 3..8, 3), "é [[a]]");
+        assert_eq!(context_around(s, 3..8, usize::MAX), "é [[a]]");
+    }
+
+    #[test]
+    fn line_start() {
+        let index = LineIndex::new("a\nb\n");
+        assert_eq!(index.position(0), Position { line: 1, col: 1 });
+        assert_eq!(index.position(2), Position { line: 2, col: 1 });
+        assert_eq!(index.position(4), Position { line: 3, col: 1 });
+    }
+}
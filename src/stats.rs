@@ -0,0 +1,20 @@
+/// Counts of what happened while parsing a document.
+///
+/// Returned by [`ParserOffsetIter::stats`](crate::ParserOffsetIter::stats),
+/// and updated as the events are produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub struct ParseStats {
+    /// the number of wikilinks
+    pub wikilinks: usize,
+    /// the number of characters removed from the targets,
+    /// see [`WikiOptions::strip_chars`](crate::WikiOptions::strip_chars)
+    pub stripped_chars: usize,
+    /// the number of wikilinks with a `..` in their target, like `[[../secret]]`,
+    /// even if they are rejected by
+    /// [`WikiOptions::reject_path_traversal`](crate::WikiOptions::reject_path_traversal)
+    pub path_traversals: usize,
+    /// the offset of the first text with a wikilink that was emitted as text, after
+    /// [`WikiOptions::max_wikilinks`](crate::WikiOptions::max_wikilinks) were found
+    pub limit_reached_at: Option<usize>,
+}
@@ -0,0 +1,175 @@
+use crate::{WikiLinkType, WikiOptions};
+
+/// An example of a form of wikilink that is recognized, to generate help
+/// for the authors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WikiSyntaxExample {
+    /// a short name of the form, like `"alias"`
+    pub name: &'static str,
+    /// the markdown source of the example, like `[[Page|alias]]`
+    pub example: &'static str,
+    /// the type of the link parsed from the example
+    pub link_type: WikiLinkType,
+    /// the target of the link parsed from the example
+    pub target: &'static str,
+    /// the example is only recognized with [`WikiOptions::embeds`]
+    pub needs_embeds: bool,
+    /// the example is only recognized with [`WikiOptions::roam_block_refs`]
+    pub needs_roam_block_refs: bool,
+    /// the example is only recognized when `{{` and `}}` are among the [`WikiOptions::delimiters`]
+    pub needs_braces: bool,
+    /// the example is only recognized with [`WikiOptions::single_bracket_links`]
+    pub needs_single_brackets: bool,
+}
+
+/// All the forms of wikilinks, with an example of each.
+///
+/// Use [`supported_syntax`] to only get the ones enabled by the options.
+pub const SUPPORTED_SYNTAX: &[WikiSyntaxExample] = &[
+    WikiSyntaxExample {
+        name: "shortcut",
+        example: "[[Page]]",
+        link_type: WikiLinkType::Shortcut,
+        target: "Page",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "alias",
+        example: "[[Page|alias]]",
+        link_type: WikiLinkType::Aliased,
+        target: "Page",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "anchor",
+        example: "[[Page#Heading]]",
+        link_type: WikiLinkType::Anchor,
+        target: "Page#Heading",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "blockref",
+        example: "[[Page#^blockid]]",
+        link_type: WikiLinkType::BlockRef,
+        target: "Page#^blockid",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "embed",
+        example: "![[image.png]]",
+        link_type: WikiLinkType::Embed,
+        target: "image.png",
+        needs_embeds: true,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "transclusion",
+        example: "![[Page]]",
+        link_type: WikiLinkType::Transclusion,
+        target: "Page",
+        needs_embeds: true,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "roam blockref",
+        example: "((abc123))",
+        link_type: WikiLinkType::RoamBlockRef,
+        target: "abc123",
+        needs_embeds: false,
+        needs_roam_block_refs: true,
+        needs_braces: false,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "braces",
+        example: "{{Page}}",
+        link_type: WikiLinkType::Shortcut,
+        target: "Page",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: true,
+        needs_single_brackets: false,
+    },
+    WikiSyntaxExample {
+        name: "single bracket",
+        example: "[Page]",
+        link_type: WikiLinkType::Shortcut,
+        target: "Page",
+        needs_embeds: false,
+        needs_roam_block_refs: false,
+        needs_braces: false,
+        needs_single_brackets: true,
+    },
+];
+
+/// The examples of [`SUPPORTED_SYNTAX`] that are recognized with `options`
+pub fn supported_syntax(options: &WikiOptions) -> impl Iterator<Item = &'static WikiSyntaxExample> {
+    let (embeds, roam_block_refs, single_brackets) =
+        (options.embeds, options.roam_block_refs, options.single_bracket_links);
+    let braces = options.delimiters.as_deref()
+        .is_some_and(|d| d.iter().any(|d| &*d.open == "{{" && &*d.close == "}}"));
+    SUPPORTED_SYNTAX.iter().filter(move |s| {
+        (embeds || !s.needs_embeds)
+            && (roam_block_refs || !s.needs_roam_block_refs)
+            && (braces || !s.needs_braces)
+            && (single_brackets || !s.needs_single_brackets)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, ParserOffsetIter};
+
+    #[test]
+    fn examples_parse() {
+        for s in SUPPORTED_SYNTAX {
+            let options = WikiOptions::new()
+                .embeds(true)
+                .roam_block_refs(true)
+                .delimiters(&[("{{", "}}", "template")])
+                .single_bracket_links(true);
+            let links: Vec<_> = ParserOffsetIter::new_with(s.example, Options::all(), options)
+                .wikilinks()
+                .collect();
+            assert_eq!(links.len(), 1, "{}", s.name);
+            assert_eq!(links[0].link_type, s.link_type, "{}", s.name);
+            assert_eq!(&*links[0].target, s.target, "{}", s.name);
+        }
+    }
+
+    #[test]
+    fn enabled_examples() {
+        assert_eq!(supported_syntax(&WikiOptions::new()).count(), 4);
+        assert_eq!(supported_syntax(&WikiOptions::new().embeds(true)).count(), 6);
+        assert_eq!(supported_syntax(&WikiOptions::new().roam_block_refs(true)).count(), 5);
+        let braces = WikiOptions::new().delimiters(&[("{{", "}}", "template")]);
+        assert_eq!(supported_syntax(&braces).count(), 5);
+        let other = WikiOptions::new().delimiters(&[("<<", ">>", "other")]);
+        assert_eq!(supported_syntax(&other).count(), 4);
+        assert_eq!(supported_syntax(&WikiOptions::new().single_bracket_links(true)).count(), 5);
+
+        let all = WikiOptions::new()
+            .embeds(true)
+            .roam_block_refs(true)
+            .delimiters(&[("{{", "}}", "template")])
+            .single_bracket_links(true);
+        assert_eq!(supported_syntax(&all).count(), SUPPORTED_SYNTAX.len());
+    }
+}
@@ -1,12 +1,24 @@
 use core::ops::Range;
+use alloc::sync::Arc;
+
+use crate::options::Delimiter;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
+    /// the alias separator, `|` by default
     Pipe,
     RBra,
     LBra,
     RRBra,
     LLBra,
+    /// `((`, only with roam block refs
+    LLPar,
+    /// `))`, only with roam block refs
+    RRPar,
+    /// the opening delimiter of the `i`th extra delimiters
+    Open(usize),
+    /// the closing delimiter of the `i`th extra delimiters
+    Close(usize),
     Word,
     NewLine,
 }
@@ -15,6 +27,7 @@ use Token::*;
 
 /// possible states of the state machine.
 /// This implementation is almost a pure DFA
+#[derive(Clone)]
 enum State {
     Default,
     AfterPipe,
@@ -23,7 +36,11 @@ enum State {
     AfterOpen3,
     AfterClose1,
     AfterClose2,
-    AfterClose3,
+    AfterParOpen1,
+    AfterParOpen2,
+    AfterParClose1,
+    AfterParClose2,
+    AfterDelimiter(Token),
     AfterSymbol,
     AfterReturn,
 }
@@ -47,7 +64,11 @@ impl State {
             AfterOpen3 => LBra,
             AfterClose1 => RBra,
             AfterClose2 => RRBra,
-            AfterClose3 => RBra,
+            AfterParOpen1 => Word,
+            AfterParOpen2 => LLPar,
+            AfterParClose1 => Word,
+            AfterParClose2 => RRPar,
+            AfterDelimiter(t) => t,
             AfterSymbol => Word,
             AfterReturn => NewLine,
             Default => return None,
@@ -56,6 +77,7 @@ impl State {
 }
 
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
     /// the state of the automata
     state: State,
@@ -69,6 +91,19 @@ pub struct Lexer<'a> {
 
     /// last time a token was returned
     last_token_end: usize,
+
+    /// the alias separator, if it is not `|`
+    separator: Option<Arc<str>>,
+
+    /// `((` and `))` are tokens
+    parens: bool,
+
+    /// the extra delimiters, like `{{` and `}}`
+    delimiters: Option<Arc<[Delimiter]>>,
+
+    /// the number of `[` that are still open since the last `[[`,
+    /// or `None` outside of a wikilink
+    depth: Option<usize>,
 }
 
 impl<'a> Lexer<'a> {
@@ -80,8 +115,71 @@ impl<'a> Lexer<'a> {
             cursor: index,
             state: State::Default,
             last_token_end: index,
+            separator: None,
+            parens: false,
+            delimiters: None,
+            depth: None,
         }
     }
+
+    /// same as `new_at`, but `separator` is recognized as a `Pipe` instead of `|`.
+    /// `separator` must not be empty
+    pub fn with_separator(source: &'a str, index: usize, separator: Option<Arc<str>>) -> Lexer<'a> {
+        Lexer {
+            separator,
+            ..Self::new_at(source, index)
+        }
+    }
+
+    /// recognizes `((` and `))` as tokens, for the roam block refs like `((uid))`
+    pub fn with_parens(mut self, enable: bool) -> Self {
+        self.parens = enable;
+        self
+    }
+
+    /// recognizes the opening and closing `delimiters` as tokens
+    pub fn with_delimiters(mut self, delimiters: Option<Arc<[Delimiter]>>) -> Self {
+        self.delimiters = delimiters;
+        self
+    }
+
+    /// updates the number of open brackets with the token `t` that is returned
+    fn track(&mut self, t: &Token) {
+        self.depth = match (t, self.depth) {
+            (LLBra, _) => Some(0),
+            (LBra, Some(d)) => Some(d + 1),
+            (RBra, Some(d)) => Some(d.saturating_sub(1)),
+            (RRBra | NewLine, _) => None,
+            (_, d) => d,
+        };
+    }
+
+    fn separator(&self) -> &str {
+        self.separator.as_deref().unwrap_or("|")
+    }
+
+    /// `self.at_separator(c)` is true if the separator starts with
+    /// the char `c` that was just read, followed by the rest of the source
+    fn at_separator(&self, c: char) -> bool {
+        let separator = self.separator();
+        separator.starts_with(c)
+            && self.source.as_str().starts_with(&separator[c.len_utf8()..])
+    }
+
+    /// the token of the extra delimiter that starts with the char `c`
+    /// that was just read, and its length
+    fn at_delimiter(&self, c: char) -> Option<(Token, usize)> {
+        let starts = |d: &str| d.starts_with(c) && self.source.as_str().starts_with(&d[c.len_utf8()..]);
+        self.delimiters.as_deref()?.iter().enumerate().find_map(|(i, d)| {
+            if starts(&d.open) {
+                Some((Open(i), d.open.len()))
+            } else if starts(&d.close) {
+                Some((Close(i), d.close.len()))
+            } else {
+                None
+            }
+        })
+    }
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -90,22 +188,31 @@ impl<'a> Iterator for Lexer<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         use State::*;
 
-        for c in self.source.by_ref() {
+        while let Some(c) = self.source.next() {
 
-            let state = std::mem::take(&mut self.state);
+            let state = core::mem::take(&mut self.state);
+            let at_separator = self.at_separator(c);
+            let delimiter = if at_separator { None } else { self.at_delimiter(c) };
 
             let (new_state, state_to_finalize) = match (c, state) {
+                (_, s) if at_separator => (AfterPipe, Some(s)),
+                (_, s) if delimiter.is_some() => (AfterDelimiter(delimiter.clone().unwrap().0), Some(s)),
                 ('\r', s)           => (s, None),
                 ('\n', s)           => (AfterReturn, Some(s)),
                 ('[', AfterOpen1)   => (AfterOpen2, None),
                 ('[', s@AfterOpen2) => (AfterOpen3, Some(s)),
                 ('[', s@AfterOpen3) => (AfterOpen3, Some(s)),
                 ('[', s)            => (AfterOpen1, Some(s)),
+                // a `]` closes the `[` that is still open inside the wikilink,
+                // so that `[[[x]]]` is tokenized as `[[`, `[`, `x`, `]`, `]]`
+                (']', s@AfterClose1) if self.depth.is_some_and(|d| d > 0) => (AfterClose1, Some(s)),
                 (']', AfterClose1)  => (AfterClose2, None),
-                (']', s@AfterClose2)=> (AfterClose3, Some(s)),
-                (']', s@AfterClose3)=> (AfterClose3, Some(s)),
+                (']', s@AfterClose2)=> (AfterClose1, Some(s)),
                 (']', s)            => (AfterClose1, Some(s)),
-                ('|', s)            => (AfterPipe, Some(s)),
+                ('(', AfterParOpen1) if self.parens => (AfterParOpen2, None),
+                ('(', s) if self.parens => (AfterParOpen1, Some(s)),
+                (')', AfterParClose1) if self.parens => (AfterParClose2, None),
+                (')', s) if self.parens => (AfterParClose1, Some(s)),
                 (_, AfterSymbol) => (AfterSymbol, None),
                 (_, s) => (AfterSymbol, Some(s))
 
@@ -113,25 +220,35 @@ impl<'a> Iterator for Lexer<'a> {
 
             self.state = new_state;
 
-            let last_cursor = self.cursor.clone();
-            self.cursor += c.len_utf8();
+            let last_cursor = self.cursor;
+            let len = match delimiter {
+                _ if at_separator => self.separator().len(),
+                Some((_, len)) => len,
+                None => c.len_utf8(),
+            };
+            // the first char of the separator or delimiter was already read
+            let rest = len - c.len_utf8();
+            self.source = self.source.as_str()[rest..].chars();
+            self.cursor += len;
 
             if let Some(t) = state_to_finalize.and_then(|x| x.finalize()) {
 
                 let position = Range {
                     end: last_cursor,
-                    start: std::mem::replace(&mut self.last_token_end, last_cursor),
+                    start: core::mem::replace(&mut self.last_token_end, last_cursor),
                 };
 
+                self.track(&t);
                 return Some((t, position));
             }
         }
 
-        if let Some(t) = std::mem::take(&mut self.state).finalize() {
+        if let Some(t) = core::mem::take(&mut self.state).finalize() {
             let position = Range {
-                start: std::mem::replace(&mut self.last_token_end, self.cursor.clone()),
+                start: core::mem::replace(&mut self.last_token_end, self.cursor.clone()),
                 end: self.cursor,
             };
+            self.track(&t);
             return Some((t, position));
         }
         None
@@ -179,6 +296,96 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_stream_closing_brackets(){
+        let source = "x]]]]";
+        let stream: Vec<(Token, _)> 
+            = Lexer::new_at(source, 0).collect();
+
+        assert_eq!(stream, 
+                   vec![
+                       (Word, 0..1), 
+                       (RRBra, 1..3), 
+                       (RRBra, 3..5), 
+                   ]
+        );
+    }
+
+    #[test]
+    fn test_stream_nested_brackets(){
+        let source = "[[[x]]]";
+        let stream: Vec<(Token, _)> 
+            = Lexer::new_at(source, 0).collect();
+
+        assert_eq!(stream, 
+                   vec![
+                       (LLBra, 0..2), 
+                       (LBra, 2..3), 
+                       (Word, 3..4), 
+                       (RBra, 4..5), 
+                       (RRBra, 5..7), 
+                   ]
+        );
+    }
+
+    #[test]
+    fn test_stream_separator(){
+        let source = "[[a::b|c]]";
+        let stream: Vec<(Token, _)> 
+            = Lexer::with_separator(source, 0, Some("::".into())).collect();
+
+        assert_eq!(stream, 
+                   vec![
+                       (LLBra, 0..2), 
+                       (Word, 2..3), 
+                       (Pipe, 3..5), 
+                       (Word, 5..8), 
+                       (RRBra, 8..10), 
+                   ]
+        );
+    }
+
+    #[test]
+    fn test_stream_parens(){
+        let source = "a ((b)) (c)";
+        let stream: Vec<(Token, _)> 
+            = Lexer::new_at(source, 0).with_parens(true).collect();
+
+        assert_eq!(stream, 
+                   vec![
+                       (Word, 0..2), 
+                       (LLPar, 2..4), 
+                       (Word, 4..5), 
+                       (RRPar, 5..7), 
+                       (Word, 7..8), 
+                       (Word, 8..9), 
+                       (Word, 9..10), 
+                       (Word, 10..11), 
+                   ]
+        );
+    }
+
+    #[test]
+    fn test_stream_delimiters(){
+        let source = "a {{b}} [[c]]";
+        let delimiters: Arc<[Delimiter]> = Arc::new([Delimiter::new("{{", "}}", "template")]);
+        let stream: Vec<(Token, _)> 
+            = Lexer::new_at(source, 0).with_delimiters(Some(delimiters)).collect();
+
+        assert_eq!(stream, 
+                   vec![
+                       (Word, 0..2), 
+                       (Open(0), 2..4), 
+                       (Word, 4..5), 
+                       (Close(0), 5..7), 
+                       (Word, 7..8), 
+                       (LLBra, 8..10), 
+                       (Word, 10..11), 
+                       (RRBra, 11..13), 
+                   ]
+        );
+    }
+
     #[test]
     fn lexer_emoji(){
         let source = "[[the url| with a strange content |😈| inside]]";
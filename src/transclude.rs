@@ -0,0 +1,217 @@
+use crate::{Item, ParserOffsetIter, WikiLink, WikiLinkType};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use alloc::vec::{self, Vec};
+use core::ops::Range;
+
+/// a document that is being inlined, or the root document
+struct Frame<'a, 'b> {
+    parser: ParserOffsetIter<'a, 'b>,
+    /// the target of the transclusion, `None` for the root document
+    target: Option<CowStr<'a>>,
+    /// the item that was parsed after the start of a paragraph
+    lookahead: Option<Item<'a>>,
+    /// the end of the paragraph of a transclusion, that must be dropped
+    skip_paragraph_end: bool,
+}
+
+/// An iterator over the `(Event, Range)` pairs of a document,
+/// where the transclusions like `![[note]]` are replaced by the events of the note.
+///
+/// Returned by [`ParserOffsetIter::transclude`]
+pub struct Transclusions<'a, 'b, F> {
+    frames: Vec<Frame<'a, 'b>>,
+    resolver: F,
+    max_depth: usize,
+    buffer: vec::IntoIter<(Event<'a>, Range<usize>)>,
+    /// the range of the transclusion in the root document
+    root_range: Range<usize>,
+}
+
+impl<'a, 'b, F: Fn(&str) -> Option<&'a str>> Transclusions<'a, 'b, F> {
+    pub(crate) fn new(parser: ParserOffsetIter<'a, 'b>, resolver: F, max_depth: usize) -> Self {
+        Self {
+            frames: alloc::vec![Frame { parser, target: None, lookahead: None, skip_paragraph_end: false }],
+            resolver,
+            max_depth,
+            buffer: Vec::new().into_iter(),
+            root_range: 0..0,
+        }
+    }
+
+    /// the content to inline instead of `link`, if it is a transclusion alone in its paragraph
+    /// that is neither too deep nor part of a cycle
+    fn resolve(&self, link: &WikiLink<'a>) -> Option<&'a str> {
+        if link.link_type != WikiLinkType::Transclusion
+            || !link.block
+            || self.frames.len() > self.max_depth
+            || self.frames.iter().any(|f| f.target.as_ref() == Some(&link.target))
+        {
+            return None
+        }
+        (self.resolver)(&link.target)
+    }
+
+    /// starts to emit the events of `content`, parsed with the same options
+    /// and the wikilinks already counted, for the limit
+    fn push(&mut self, link: WikiLink<'a>, content: &'a str) {
+        let parent = &mut self.frames.last_mut().unwrap().parser;
+        let mut parser = ParserOffsetIter::new_shared(content, parent.options, parent.wiki_options.clone());
+        parser.stats.wikilinks = parent.stats.wikilinks;
+        if self.frames.len() == 1 {
+            self.root_range = link.range;
+        }
+        self.frames.push(Frame { parser, target: Some(link.target), lookahead: None, skip_paragraph_end: false });
+    }
+
+    /// the next item of the innermost document, where the start of the paragraph
+    /// of a transclusion is replaced by the transclusion
+    fn next_item(&mut self) -> Option<Item<'a>> {
+        let frame = self.frames.last_mut().unwrap();
+        if let Some(item) = frame.lookahead.take() {
+            return Some(item)
+        }
+        let item = frame.parser.next_item()?;
+        if !matches!(item, Item::Event(Event::Start(Tag::Paragraph), _)) {
+            return Some(item)
+        }
+        let next = frame.parser.next_item();
+        match next {
+            Some(Item::Link(l)) => match self.resolve(&l) {
+                Some(content) => {
+                    self.frames.last_mut().unwrap().skip_paragraph_end = true;
+                    self.push(l, content);
+                    self.next_item()
+                }
+                None => {
+                    self.frames.last_mut().unwrap().lookahead = Some(Item::Link(l));
+                    Some(item)
+                }
+            },
+            next => {
+                self.frames.last_mut().unwrap().lookahead = next;
+                Some(item)
+            }
+        }
+    }
+}
+
+impl<'a, 'b, F: Fn(&str) -> Option<&'a str>> Iterator for Transclusions<'a, 'b, F> {
+    type Item = (Event<'a>, Range<usize>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (e, r) = match self.buffer.next() {
+                Some(x) => x,
+                None => match self.next_item() {
+                    Some(Item::Event(Event::End(TagEnd::Paragraph), _))
+                        if self.frames.last().unwrap().skip_paragraph_end =>
+                    {
+                        self.frames.last_mut().unwrap().skip_paragraph_end = false;
+                        continue
+                    }
+                    Some(Item::Event(e, r)) => (e, r),
+                    Some(Item::Link(l)) => {
+                        self.buffer = self.frames.last().unwrap().parser.link_events(l);
+                        continue
+                    }
+                    None if self.frames.len() > 1 => {
+                        let wikilinks = self.frames.pop().unwrap().parser.stats.wikilinks;
+                        self.frames.last_mut().unwrap().parser.stats.wikilinks = wikilinks;
+                        continue
+                    }
+                    None => return None,
+                },
+            };
+            // the events of a transcluded note come from the transclusion in the root document
+            let r = if self.frames.len() > 1 { self.root_range.clone() } else { r };
+            return Some((e, r))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Options, ParserOffsetIter, WikiOptions, TRANSCLUSION_MARKER};
+    use pulldown_cmark::{Event, Tag};
+
+    fn texts(source: &'static str, resolver: impl Fn(&str) -> Option<&'static str>) -> Vec<String> {
+        let wiki_options = WikiOptions::new().embeds(true);
+        ParserOffsetIter::new_with(source, Options::all(), wiki_options)
+            .transclude(resolver, 4)
+            .filter_map(|(e, _)| match e {
+                Event::Text(t) => Some(t.to_string()),
+                Event::Start(Tag::Paragraph) => Some("<p>".into()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolved_transclusion() {
+        let s = "see:\n\n![[note]]\n\nend";
+        let resolver = |t: &str| (t == "note").then_some("the note\n\nand [[a link]]");
+        assert_eq!(texts(s, resolver), ["<p>", "see:", "<p>", "the note", "<p>", "and ", "a link", "<p>", "end"]);
+
+        let wiki_options = WikiOptions::new().embeds(true);
+        let ranges: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .transclude(resolver, 4)
+            .map(|(_, r)| r)
+            .collect();
+        assert_eq!(ranges[3..ranges.len() - 3], vec![6..15; 9]);
+    }
+
+    #[test]
+    fn unresolved_transclusion() {
+        let s = "![[missing]]\n\ntext ![[note]]";
+        let wiki_options = WikiOptions::new().embeds(true);
+        let titles: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .transclude(|t: &str| (t == "note").then_some("the note"), 4)
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Link { title, .. }) => Some(title),
+                _ => None,
+            })
+            .collect();
+
+        // a missing note, and a transclusion inside a paragraph, are kept as links
+        assert_eq!(titles, [TRANSCLUSION_MARKER.into(), TRANSCLUSION_MARKER.into()]);
+    }
+
+    #[test]
+    fn transclusion_cycle() {
+        let resolver = |t: &str| match t {
+            "a" => Some("in a\n\n![[b]]"),
+            "b" => Some("in b\n\n![[a]]"),
+            _ => None,
+        };
+        // `a` is not inlined again inside `b`
+        assert_eq!(texts("![[a]]", resolver), ["<p>", "in a", "<p>", "in b", "<p>", "a"]);
+
+        // only 4 transclusions are nested
+        let resolver = |t: &str| match t {
+            "a" => Some("![[b]]"),
+            "b" => Some("![[c]]"),
+            "c" => Some("![[d]]"),
+            "d" => Some("![[e]]"),
+            _ => Some("end"),
+        };
+        assert_eq!(texts("![[a]]", resolver), ["<p>", "e"]);
+    }
+
+    #[test]
+    fn max_wikilinks_across_notes() {
+        let s = "![[a]]\n\n![[a]]\n\n[[x]]";
+        let resolver = |t: &str| (t == "a").then_some("[[b]] [[c]]");
+        let wiki_options = WikiOptions::new().embeds(true).max_wikilinks(4);
+        let targets: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), wiki_options)
+            .transclude(resolver, 4)
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+                _ => None,
+            })
+            .collect();
+
+        // the two transclusions and the links of the first note are counted,
+        // the second note and the rest of the document are only text
+        assert_eq!(targets, ["b", "c"]);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::WikiLink;
+use pulldown_cmark::CowStr;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+
+/// A transformation of the targets of wikilinks, like slugifying or prefixing.
+///
+/// Transforms compose with [`then`](TargetTransform::then),
+/// and a closure `Fn(&str) -> String` is a transform.
+pub trait TargetTransform {
+    fn transform<'a>(&self, target: CowStr<'a>) -> CowStr<'a>;
+
+    /// `a.then(b)` is the transform that applies `a`, then `b` on the result
+    fn then<T: TargetTransform>(self, next: T) -> Then<Self, T>
+    where
+        Self: Sized,
+    {
+        Then(self, next)
+    }
+}
+
+impl<F: Fn(&str) -> String> TargetTransform for F {
+    fn transform<'a>(&self, target: CowStr<'a>) -> CowStr<'a> {
+        self(&target).into()
+    }
+}
+
+/// Two transforms applied one after the other.
+///
+/// Returned by [`TargetTransform::then`]
+pub struct Then<A, B>(A, B);
+
+impl<A: TargetTransform, B: TargetTransform> TargetTransform for Then<A, B> {
+    fn transform<'a>(&self, target: CowStr<'a>) -> CowStr<'a> {
+        self.1.transform(self.0.transform(target))
+    }
+}
+
+/// A change of each wikilink, once it is parsed, for the behaviors that are
+/// specific to a project, like checking that the targets exist.
+///
+/// See [`WikiOptions::processor`](crate::WikiOptions::processor)
+pub trait WikiLinkProcessor {
+    fn process<'a>(&self, link: WikiLink<'a>) -> ProcessedLink<'a>;
+}
+
+impl<P: WikiLinkProcessor + ?Sized> WikiLinkProcessor for Box<P> {
+    fn process<'a>(&self, link: WikiLink<'a>) -> ProcessedLink<'a> {
+        (**self).process(link)
+    }
+}
+
+/// What a [`WikiLinkProcessor`] makes of a wikilink
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcessedLink<'a> {
+    /// the link is emitted, with the changes of the processor
+    Link(WikiLink<'a>),
+    /// the link is emitted, with the [`BROKEN_LINK_MARKER`](crate::BROKEN_LINK_MARKER) as title
+    Broken(WikiLink<'a>),
+    /// only the text that the link displays is emitted
+    Drop(WikiLink<'a>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, Options, ParserOffsetIter, Tag, WikiOptions, BROKEN_LINK_MARKER, WIKI_MARKER};
+
+    #[test]
+    fn compose() {
+        let lower = |s: &str| s.to_lowercase();
+        let prefix = |s: &str| format!("/wiki/{s}");
+
+        let t = lower.then(prefix);
+        assert_eq!(t.transform("My Note".into()), "/wiki/my note".into());
+    }
+
+    struct Uppercase;
+
+    impl WikiLinkProcessor for Uppercase {
+        fn process<'a>(&self, link: WikiLink<'a>) -> ProcessedLink<'a> {
+            match &*link.target {
+                "missing" => ProcessedLink::Broken(link),
+                "private" => ProcessedLink::Drop(link),
+                t => ProcessedLink::Link(WikiLink { target: t.to_uppercase().into(), ..link }),
+            }
+        }
+    }
+
+    #[test]
+    fn processor() {
+        let s = "[[a|b]] [[missing]] [[private|secret]]";
+        let wiki_options = WikiOptions::new().processor(Box::new(Uppercase) as Box<dyn WikiLinkProcessor + Send + Sync>);
+        let mut parser = ParserOffsetIter::new_with(s, Options::all(), wiki_options);
+        let links: Vec<_> = parser
+            .by_ref()
+            .filter_map(|(e, _)| match e {
+                Event::Start(Tag::Link { dest_url, title, .. }) => Some((dest_url, title)),
+                Event::Text(t) if &*t == "secret" => Some((t, "".into())),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(links, vec![
+                   ("A".into(), WIKI_MARKER.into()),
+                   ("missing".into(), BROKEN_LINK_MARKER.into()),
+                   ("secret".into(), "".into()),
+        ]);
+        assert_eq!(parser.stats().wikilinks, 2);
+    }
+}
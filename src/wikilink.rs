@@ -0,0 +1,526 @@
+use crate::WikiOptions;
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+
+use core::ops::Range;
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The title of the links and images that come from a wikilink
+pub const WIKI_MARKER: &str = "wiki";
+
+/// The title of the links that come from a transclusion, like `![[Note]]`
+pub const TRANSCLUSION_MARKER: &str = "wiki-transclusion";
+
+/// The title of the links that come from a roam block ref, like `((uid))`
+pub const ROAM_BLOCK_REF_MARKER: &str = "wiki-block-ref";
+
+/// The title of the links that point to nothing, as told by a
+/// [`WikiLinkProcessor`](crate::WikiLinkProcessor)
+pub const BROKEN_LINK_MARKER: &str = "wiki-broken";
+
+/// The different forms of wikilinks.
+///
+/// When several apply, the first one in this order is chosen:
+/// `![[Page#Heading|alias]]` is a `Transclusion`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WikiLinkType {
+    /// `![[image.png]]`, an embed of an image,
+    /// see [`WikiOptions::embeds`](crate::WikiOptions::embeds)
+    Embed,
+    /// `![[Note]]`, an embed of anything that is not an image,
+    /// see [`WikiOptions::image_extensions`](crate::WikiOptions::image_extensions)
+    Transclusion,
+    /// `[[Page#^blockid]]` or `[[^blockid]]`
+    BlockRef,
+    /// `((uid))`, a block of another page in Roam,
+    /// see [`WikiOptions::roam_block_refs`](crate::WikiOptions::roam_block_refs)
+    RoamBlockRef,
+    /// `[[Page#Heading]]`
+    Anchor,
+    /// `[[target|alias]]`
+    Aliased,
+    /// `[[target]]`
+    Shortcut,
+}
+
+impl WikiLinkType {
+    pub(crate) fn classify(embed: bool, image: bool, target: &str, aliased: bool) -> Self {
+        if embed && image {
+            Self::Embed
+        } else if embed {
+            Self::Transclusion
+        } else if target.contains("#^") || target.starts_with('^') {
+            Self::BlockRef
+        } else if target.contains('#') {
+            Self::Anchor
+        } else if aliased {
+            Self::Aliased
+        } else {
+            Self::Shortcut
+        }
+    }
+}
+
+/// The parameters given after the pipe of an embed.
+///
+/// In `![[image.png|100]]`, the width is 100.
+/// In `![[image.png|100x200]]`, the width is 100 and the height is 200.
+/// In `![[image.png|caption|300]]`, the alt text is `caption` and the width is 300.
+///
+/// Each field separated by a pipe is a size if it is `width` or `widthxheight`,
+/// and the alt text otherwise. Empty fields are ignored, and when several
+/// fields are sizes or alt texts, the last one wins.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct EmbedParams {
+    pub alt: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl EmbedParams {
+    /// parses the fields at `range` of the source, like `caption|300`.
+    /// Returns the parameters and the range of the alt text,
+    /// or `None` if all the fields are empty
+    pub(crate) fn parse(source: &str, range: Range<usize>) -> Option<(Self, Option<Range<usize>>)> {
+        let mut params = Self::default();
+        let mut alt_range = None;
+        let mut start = range.start;
+        for field in source[range].split('|') {
+            let trimmed = field.trim_start();
+            let field_start = start + field.len() - trimmed.len();
+            let trimmed = trimmed.trim_end();
+            start += field.len() + 1;
+            if trimmed.is_empty() {
+                continue
+            }
+            match Self::parse_size(trimmed) {
+                Some((width, height)) => {
+                    params.width = Some(width);
+                    params.height = height;
+                }
+                None => {
+                    params.alt = Some(trimmed.into());
+                    alt_range = Some(field_start..field_start + trimmed.len());
+                }
+            }
+        }
+        (params != Self::default()).then_some((params, alt_range))
+    }
+
+    /// parses a size, either `width` or `widthxheight`
+    fn parse_size(s: &str) -> Option<(u32, Option<u32>)> {
+        match s.split_once('x') {
+            Some((w, h)) => Some((w.parse().ok()?, Some(h.parse().ok()?))),
+            None => Some((s.parse().ok()?, None)),
+        }
+    }
+}
+
+/// The parts of the target of a wikilink, like `Page#Heading` or `Page#^blockid`,
+/// as ranges of the source that don't include the `#` or `^`.
+///
+/// The target is only split at the delimiters enabled by
+/// [`WikiOptions::split_fragments`](crate::WikiOptions::split_fragments) and
+/// [`WikiOptions::split_block_refs`](crate::WikiOptions::split_block_refs),
+/// otherwise the page is the whole target.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct TargetParts {
+    /// `Page`, empty for a link to the same page like `[[#Heading]]`
+    pub page: Range<usize>,
+    /// `Heading` in `Page#Heading`
+    pub fragment: Option<Range<usize>>,
+    /// `blockid` in `Page#^blockid` or `^blockid`
+    pub block: Option<Range<usize>>,
+}
+
+impl TargetParts {
+    fn shifted(self, offset: usize) -> Self {
+        let shift = |r: Range<usize>| r.start + offset..r.end + offset;
+        Self {
+            page: shift(self.page),
+            fragment: self.fragment.map(shift),
+            block: self.block.map(shift),
+        }
+    }
+}
+
+/// A wikilink, as found in the source.
+///
+/// Every range is a byte range inside the original source,
+/// so that editors can style each part of the link separately.
+///
+/// Two links are equal, and are ordered, by their [`key`](WikiLink::key) only,
+/// so that the same link at different places is deduped by a `HashSet` or a `BTreeSet`.
+#[derive(Debug, Clone)]
+pub struct WikiLink<'a> {
+    /// the destination of the link, ie `url` in `[[url|alias]]`
+    pub target: CowStr<'a>,
+    /// the text that is displayed, ie `alias` in `[[url|alias]]`,
+    /// or `url` in `[[url]]`
+    pub display: CowStr<'a>,
+    /// the id of the emitted link, empty unless
+    /// [`WikiOptions::generate_ids`](crate::WikiOptions::generate_ids) is enabled
+    pub id: CowStr<'a>,
+    /// the whole link, from the opening `[[` to the closing `]]`
+    pub range: Range<usize>,
+    /// the opening `[[`
+    pub open_delim_range: Range<usize>,
+    /// the closing `]]`
+    pub close_delim_range: Range<usize>,
+    /// the text between `[[` and either `|` or `]]`
+    pub target_range: Range<usize>,
+    /// the target is written with spaces around it, like in `[[ page ]]`.
+    /// They are outside of `target_range` with
+    /// [`WikiOptions::trim_spaces`](crate::WikiOptions::trim_spaces)
+    pub padded: bool,
+    /// the parts of the target
+    pub parts: TargetParts,
+    /// the first separator, usually `|`, if there is one
+    pub pipe_range: Option<Range<usize>>,
+    /// the text between `|` and `]]`, if there is one
+    pub alias_range: Option<Range<usize>>,
+    /// the text that is displayed:
+    /// usually the alias if there is one, or the target
+    pub display_range: Range<usize>,
+    /// the target has an explicit scheme, and is emitted as a normal link.
+    /// See [`WikiOptions::external_links`](crate::WikiOptions::external_links)
+    pub external: bool,
+    pub link_type: WikiLinkType,
+    /// the parameters of an embed, like its size in `![[image.png|100x200]]`
+    pub embed_params: Option<EmbedParams>,
+    /// the kind of the extra delimiters of the link, like `template` for `{{a}}`,
+    /// or `None` for `[[a]]`. See [`WikiOptions::delimiters`](crate::WikiOptions::delimiters)
+    pub kind: Option<Arc<str>>,
+    /// the target doesn't exist, as told by a
+    /// [`WikiLinkProcessor`](crate::WikiLinkProcessor)
+    pub broken: bool,
+    /// the link is the only content of its paragraph,
+    /// so it can be rendered as a block, like a transclusion
+    pub block: bool,
+}
+
+impl PartialEq for WikiLink<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for WikiLink<'_> {}
+
+impl core::hash::Hash for WikiLink<'_> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialOrd for WikiLink<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WikiLink<'_> {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl<'a> WikiLink<'a> {
+    /// what makes two links the same, wherever they are in the source:
+    /// their target and what they display. It is what links are compared on
+    ///
+    /// ```
+    /// use pulldown_cmark_wikilink::{Options, ParserOffsetIter};
+    /// use std::collections::BTreeSet;
+    ///
+    /// let s = "[[b]] [[a]] [[b]] [[a|c]]";
+    /// let links: BTreeSet<_> = ParserOffsetIter::new_ext(s, Options::empty(), true).wikilinks().collect();
+    ///
+    /// let keys: Vec<_> = links.iter().map(|l| l.key()).collect();
+    /// assert_eq!(keys, [("a", "a"), ("a", "c"), ("b", "b")]);
+    /// ```
+    pub fn key(&self) -> (&str, &str) {
+        (&self.target, &self.display)
+    }
+
+    /// the text of the whole link in `source`, with its delimiters, like `[[a|b]]`.
+    ///
+    /// `source` is the text that the ranges refer to, including the base offset
+    pub fn source_text<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.range.clone()]
+    }
+
+    /// the page of the target, ie `Page` in `[[Page#Heading]]`.
+    /// It is empty for a link to the same page, like `[[#Heading]]`
+    pub fn page(&self) -> &str {
+        match self.target.split_once('#') {
+            Some((page, _)) => page,
+            None => &self.target,
+        }
+    }
+
+    /// the part of the target after the `#`, ie `Heading` in `[[Page#Heading]]`,
+    /// or `^blockid` in `[[Page#^blockid]]`
+    pub fn fragment(&self) -> Option<&str> {
+        self.target.split_once('#').map(|(_, fragment)| fragment)
+    }
+
+    /// the namespace of the target, ie `Category` in `[[Category:Foo]]`, as in MediaWiki.
+    ///
+    /// The target is split at its first `:`, if it is followed by a letter,
+    /// so that `https://example.com` has no namespace
+    pub fn namespace(&self) -> Option<&str> {
+        self.split_namespace().map(|(namespace, _)| namespace)
+    }
+
+    /// the target without its [namespace](Self::namespace), ie `Foo` in `[[Category:Foo]]`
+    pub fn name(&self) -> &str {
+        self.split_namespace().map_or(&self.target, |(_, name)| name)
+    }
+
+    fn split_namespace(&self) -> Option<(&str, &str)> {
+        let (namespace, name) = self.target.split_once(':')?;
+        let valid = !namespace.is_empty()
+            && !namespace.contains(['/', '#'])
+            && name.starts_with(char::is_alphabetic);
+        valid.then_some((namespace, name))
+    }
+
+    /// shifts every range of the link by `offset` bytes
+    pub(crate) fn shifted(self, offset: usize) -> Self {
+        let shift = |r: Range<usize>| r.start + offset..r.end + offset;
+        Self {
+            range: shift(self.range),
+            open_delim_range: shift(self.open_delim_range),
+            close_delim_range: shift(self.close_delim_range),
+            target_range: shift(self.target_range),
+            parts: self.parts.shifted(offset),
+            pipe_range: self.pipe_range.map(shift),
+            alias_range: self.alias_range.map(shift),
+            display_range: shift(self.display_range),
+            ..self
+        }
+    }
+
+    /// `link.into_events()` returns the events that represent
+    /// the wikilink in the pulldown-cmark event stream
+    pub fn into_events(self) -> vec::IntoIter<(Event<'a>, Range<usize>)> {
+        let display_range = self.display_range.clone();
+
+        let link_type = LinkType::Inline;
+        let dest_url = self.target;
+        let title = match self.link_type {
+            _ if self.external => "".into(),
+            _ if self.broken => BROKEN_LINK_MARKER.into(),
+            _ if self.kind.is_some() => String::from(&*self.kind.unwrap()).into(),
+            WikiLinkType::Transclusion => TRANSCLUSION_MARKER.into(),
+            WikiLinkType::RoamBlockRef => ROAM_BLOCK_REF_MARKER.into(),
+            _ => WIKI_MARKER.into(),
+        };
+        let id = self.id;
+
+        let (opening_tag, closing_tag) = match self.link_type {
+            WikiLinkType::Embed => (
+                Event::Start(Tag::Image { link_type, dest_url, title, id }),
+                Event::End(TagEnd::Image),
+            ),
+            _ => (
+                Event::Start(Tag::Link { link_type, dest_url, title, id }),
+                Event::End(TagEnd::Link),
+            ),
+        };
+
+        vec![
+            (opening_tag, self.range.clone()),
+            (Event::Text(self.display), display_range),
+            (closing_tag, self.range),
+        ]
+        .into_iter()
+    }
+
+    /// the single text event that the link is emitted as, with
+    /// [`passthrough_wikilinks`](WikiOptions::passthrough_wikilinks) or
+    /// [`links_as_text`](WikiOptions::links_as_text), or `None` if it is emitted as a link.
+    ///
+    /// `source` is the text where the link was found, that starts at `base` in the document
+    pub(crate) fn as_text(
+        &self,
+        source: &'a str,
+        base: usize,
+        options: &WikiOptions,
+    ) -> Option<(Event<'a>, Range<usize>)> {
+        if options.passthrough_wikilinks {
+            let text = crate::slice(source, self.range.start - base..self.range.end - base);
+            return Some((Event::Text(text.into()), self.range.clone()))
+        }
+        if options.links_as_text {
+            return Some((Event::Text(self.display.clone()), self.display_range.clone()))
+        }
+        None
+    }
+
+    /// the events that the link is emitted as, according to `options`:
+    /// its [text](Self::as_text), or else its [events](Self::into_events)
+    pub(crate) fn into_output_events(
+        self,
+        source: &'a str,
+        base: usize,
+        options: &WikiOptions,
+    ) -> vec::IntoIter<(Event<'a>, Range<usize>)> {
+        match self.as_text(source, base, options) {
+            Some(text) => vec![text].into_iter(),
+            None => self.into_events(),
+        }
+    }
+}
+
+/// `title_case(s)` is `s` with the first letter of each word in uppercase
+pub(crate) fn title_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut word_start = true;
+    for c in s.chars() {
+        if word_start {
+            result.extend(c.to_uppercase());
+        } else {
+            result.push(c);
+        }
+        word_start = c.is_whitespace();
+    }
+    result
+}
+
+/// `resolve_relative(dir, target)` is the path of `target` from the root,
+/// if it is relative to `dir`, ie it starts with `./` or `../`.
+///
+/// A `..` that would go above the root is ignored, and the fragment is kept.
+pub(crate) fn resolve_relative(dir: &str, target: &str) -> Option<String> {
+    if !target.starts_with("./") && !target.starts_with("../") {
+        return None
+    }
+    let (path, fragment) = match target.find('#') {
+        Some(i) => target.split_at(i),
+        None => (target, ""),
+    };
+
+    let mut segments: Vec<&str> = dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+
+    let root = if dir.starts_with('/') { "/" } else { "" };
+    Some(format!("{root}{}{fragment}", segments.join("/")))
+}
+
+/// `has_traversal(target)` is true if the page of `target` has a `..` segment,
+/// so that it could point outside of the folder of the notes
+pub(crate) fn has_traversal(target: &str) -> bool {
+    let page = target.split('#').next().unwrap_or_default();
+    page.split(['/', '\\']).any(|s| s == "..")
+}
+
+/// `slug(s)` is `s` in lowercase, where each run of characters
+/// that are not alphanumeric is replaced by a single `-`
+pub(crate) fn slug(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for word in s.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        if !result.is_empty() {
+            result.push('-');
+        }
+        result.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    result
+}
+
+/// `github_slug(s)` is the id that GitHub gives to a heading `s`:
+/// in lowercase, each space replaced by a `-`, and the punctuation
+/// other than `-` and `_` removed
+pub(crate) fn github_slug(s: &str) -> String {
+    s.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            _ if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// `has_scheme(target)` is true if `target` looks like an url,
+/// ie `scheme://...` or `mailto:...`
+pub(crate) fn has_scheme(target: &str) -> bool {
+    if target.starts_with("mailto:") {
+        return true
+    }
+    match target.split_once("://") {
+        Some((scheme, _)) => !scheme.is_empty() 
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_relative;
+    use crate::{parse_single_wikilink, Options, ParserOffsetIter, WikiOptions};
+
+    #[test]
+    fn page_and_fragment() {
+        let parts = |s| {
+            let link = parse_single_wikilink(s).unwrap();
+            (link.page().to_string(), link.fragment().map(str::to_string))
+        };
+
+        assert_eq!(parts("[[Page]]"), ("Page".into(), None));
+        assert_eq!(parts("[[Page#Heading|alias]]"), ("Page".into(), Some("Heading".into())));
+        assert_eq!(parts("[[Page#^blockid]]"), ("Page".into(), Some("^blockid".into())));
+        assert_eq!(parts("[[#Heading]]"), ("".into(), Some("Heading".into())));
+        assert_eq!(parts("[[^blockid]]"), ("".into(), Some("^blockid".into())));
+        assert_eq!(parts("[[Page#a#b]]"), ("Page".into(), Some("a#b".into())));
+    }
+
+    #[test]
+    fn namespace() {
+        let parts = |s| {
+            let link = parse_single_wikilink(s).unwrap();
+            (link.namespace().map(str::to_string), link.name().to_string())
+        };
+
+        assert_eq!(parts("[[Category:Foo]]"), (Some("Category".into()), "Foo".into()));
+        assert_eq!(parts("[[Template:Bar|x]]"), (Some("Template".into()), "Bar".into()));
+        assert_eq!(parts("[[NoNamespace]]"), (None, "NoNamespace".into()));
+        assert_eq!(parts("[[http://example.com]]"), (None, "http://example.com".into()));
+        assert_eq!(parts("[[Time: 10:30]]"), (None, "Time: 10:30".into()));
+        assert_eq!(parts("[[:Foo]]"), (None, ":Foo".into()));
+    }
+
+    #[test]
+    fn source_text() {
+        let s = "see [[a|b]] and ![[c]]";
+        let links: Vec<_> = ParserOffsetIter::new_with(s, Options::all(), WikiOptions::new().embeds(true))
+            .wikilinks()
+            .collect();
+
+        assert_eq!(links[0].source_text(s), "[[a|b]]");
+        assert_eq!(links[1].source_text(s), "![[c]]");
+    }
+
+    #[test]
+    fn relative_targets() {
+        assert_eq!(resolve_relative("notes/sub", "../x"), Some("notes/x".into()));
+        assert_eq!(resolve_relative("notes/sub", "./y"), Some("notes/sub/y".into()));
+        assert_eq!(resolve_relative("/notes/sub/", "./a/../y#h"), Some("/notes/sub/y#h".into()));
+        assert_eq!(resolve_relative("notes", "../../../x"), Some("x".into()));
+        assert_eq!(resolve_relative("notes", "x"), None);
+    }
+}